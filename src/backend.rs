@@ -0,0 +1,44 @@
+//! The burn backend chosen with cargo features.
+//!
+//! `tch` (the default) uses libtorch and can train on a GPU, `ndarray` is pure Rust and CPU only:
+//!
+//! ```sh
+//! cargo test --no-default-features --features ndarray
+//! ```
+
+use burn::backend::Autodiff;
+
+#[cfg(not(any(feature = "tch", feature = "ndarray")))]
+compile_error!("Enable a burn backend with the feature `tch` or `ndarray`");
+
+#[cfg(feature = "tch")]
+pub type DefaultBackend = burn::backend::LibTorch;
+
+#[cfg(all(feature = "ndarray", not(feature = "tch")))]
+pub type DefaultBackend = burn::backend::NdArray;
+
+pub type TrainingBackend = Autodiff<DefaultBackend>;
+
+pub type Device = <DefaultBackend as burn::tensor::backend::Backend>::Device;
+
+/// The first GPU if there is one and `cpu_only` isn't set.
+#[cfg(feature = "tch")]
+pub fn device(cpu_only: bool) -> Device {
+    use burn::backend::libtorch::LibTorchDevice;
+    if cpu_only {
+        LibTorchDevice::Cpu
+    } else {
+        #[cfg(not(target_os = "macos"))]
+        let device = LibTorchDevice::Cuda(0);
+        // MacOs Mps too slow
+        #[cfg(target_os = "macos")]
+        let device = LibTorchDevice::Cpu;
+        device
+    }
+}
+
+/// Always the CPU, `ndarray` has no GPU support.
+#[cfg(all(feature = "ndarray", not(feature = "tch")))]
+pub fn device(_cpu_only: bool) -> Device {
+    burn::backend::ndarray::NdArrayDevice::Cpu
+}
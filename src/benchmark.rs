@@ -0,0 +1,210 @@
+//! Benchmarks which `TDTrainer` plays during training, and the gating of the best model.
+
+use crate::dicegen::FastrandDice;
+use crate::duel::Duel;
+use crate::evaluator::{Evaluator, HyperEvaluator};
+use crate::fstate::FState;
+use crate::probabilities::{Probabilities, ResultCounter};
+use bkgm::{Backgammon, Dice, Hypergammon, State};
+use burn::config::Config;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Opponents the model is benchmarked against.
+#[derive(Config, Debug, Copy, PartialEq, Eq)]
+pub enum Opponent {
+    Random,
+    PubEval,
+    /// The hypergammon database, only for hypergammon.
+    Hyper,
+    /// The best model so far, see `BenchmarkConfig::gate`.
+    Best,
+}
+
+impl Opponent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Opponent::Random => "random",
+            Opponent::PubEval => "pubeval",
+            Opponent::Hyper => "hyper",
+            Opponent::Best => "best",
+        }
+    }
+}
+
+impl FromStr for Opponent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "random" => Ok(Opponent::Random),
+            "pubeval" => Ok(Opponent::PubEval),
+            "hyper" => Ok(Opponent::Hyper),
+            "best" => Ok(Opponent::Best),
+            _ => Err(format!(
+                "Unknown opponent '{}', expected random, pubeval, hyper or best",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Config)]
+pub struct BenchmarkConfig {
+    /// Episodes between benchmarks.
+    #[config(default = 5000)]
+    pub interval: usize,
+    /// Games against each opponent, played in pairs with the same dice.
+    #[config(default = 1000)]
+    pub games: usize,
+    #[config(default = "vec![Opponent::Random, Opponent::PubEval, Opponent::Best]")]
+    pub opponents: Vec<Opponent>,
+    /// Win probability against `Opponent::Best` which makes the model the new best one.
+    /// Without `Opponent::Best` among the opponents the best model never changes.
+    #[config(default = 0.53)]
+    pub gate: f32,
+    /// Episodes without a new best model after which training rolls back to the best one.
+    #[config(default = "Some(100_000)")]
+    pub patience: Option<usize>,
+}
+
+impl BenchmarkConfig {
+    /// Fails if one of the opponents can't play `G`.
+    pub fn validate<G: Benchmark>(&self) -> Result<(), String> {
+        if self.opponents.contains(&Opponent::Hyper) && !G::HAS_HYPER {
+            return Err(format!(
+                "The {} opponent only plays hypergammon",
+                Opponent::Hyper.name()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Games which can be benchmarked.
+pub trait Benchmark: State + Send + Sync {
+    /// What plays `Opponent::Hyper`.
+    type Hyper: Evaluator<FState<Self>>;
+    /// Whether `hyper` can play this game at all.
+    const HAS_HYPER: bool;
+
+    /// Loaded once per training run, the database is large.
+    fn hyper() -> Result<Self::Hyper, String>;
+}
+
+/// `Opponent::Hyper` of games it doesn't know, can't be created.
+pub enum NoHyper {}
+
+impl<G: State> Evaluator<G> for NoHyper {
+    fn best_position(&self, _pos: &G, _dice: &Dice) -> G {
+        match *self {}
+    }
+}
+
+impl Benchmark for Hypergammon {
+    type Hyper = HyperEvaluator;
+    const HAS_HYPER: bool = true;
+
+    fn hyper() -> Result<Self::Hyper, String> {
+        HyperEvaluator::new().ok_or_else(|| "Failed to load the hypergammon database".to_string())
+    }
+}
+
+impl Benchmark for Backgammon {
+    type Hyper = NoHyper;
+    const HAS_HYPER: bool = false;
+
+    fn hyper() -> Result<Self::Hyper, String> {
+        Err("The hypergammon database can't play backgammon".to_string())
+    }
+}
+
+/// One line of `benchmarks.csv`, the probabilities are cumulative like those of `Probabilities`.
+#[derive(Serialize)]
+pub struct BenchmarkRow {
+    pub episode: usize,
+    pub opponent: &'static str,
+    pub equity: f32,
+    pub win: f32,
+    pub win_g: f32,
+    pub win_b: f32,
+    pub lose_g: f32,
+    pub lose_b: f32,
+    /// Episode of the best model after this benchmark.
+    pub best_episode: usize,
+    /// Whether training went back to the best model.
+    pub rollback: bool,
+}
+
+impl BenchmarkRow {
+    pub fn new(
+        episode: usize,
+        opponent: Opponent,
+        probs: &Probabilities,
+        best_episode: usize,
+        rollback: bool,
+    ) -> Self {
+        Self {
+            episode,
+            opponent: opponent.name(),
+            equity: probs.equity(),
+            win: probs.win_prob(),
+            win_g: probs.win_g + probs.win_b,
+            win_b: probs.win_b,
+            lose_g: probs.lose_g + probs.lose_b,
+            lose_b: probs.lose_b,
+            best_episode,
+            rollback,
+        }
+    }
+}
+
+/// `games` games of `model` against `opponent`, the probabilities are `model`'s.
+/// The dice come from `seed`, so both sides see the same luck in every pair of games.
+pub fn play<G: State, E: Evaluator<G>, O: Evaluator<G>>(
+    model: E,
+    opponent: O,
+    games: usize,
+    seed: u64,
+) -> Probabilities {
+    let duel = Duel::new(model, opponent);
+    let mut dicegen = FastrandDice::with_seed(seed);
+    let mut results = ResultCounter::default();
+    for _ in 0..games.div_ceil(2) {
+        results = results.combine(&duel.single_duel(&mut dicegen));
+    }
+    results.probabilities()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::benchmark::{Benchmark, BenchmarkConfig, Opponent};
+    use bkgm::{Backgammon, Hypergammon};
+    use std::str::FromStr;
+
+    #[test]
+    fn opponent_names() {
+        for opponent in [
+            Opponent::Random,
+            Opponent::PubEval,
+            Opponent::Hyper,
+            Opponent::Best,
+        ] {
+            assert_eq!(Opponent::from_str(opponent.name()), Ok(opponent));
+        }
+        assert!(Opponent::from_str("gnubg").is_err());
+    }
+
+    #[test]
+    fn no_hyper_for_backgammon() {
+        assert!(Backgammon::hyper().is_err());
+    }
+
+    #[test]
+    fn hyper_is_validated() {
+        let config = BenchmarkConfig::new().with_opponents(vec![Opponent::Hyper]);
+        assert!(config.validate::<Backgammon>().is_err());
+        assert!(config.validate::<Hypergammon>().is_ok());
+        assert!(BenchmarkConfig::new().validate::<Backgammon>().is_ok());
+    }
+}
@@ -1,22 +1,40 @@
+use std::path::PathBuf;
+
 use bkgm::Hypergammon;
+use clap::Parser;
 use td_gammon::{
+    cpu::CpuModel,
     duel::duel,
     evaluator::{HyperEvaluator, PubEval, RandomEvaluator},
     fstate::FState,
 };
 
-pub mod train;
+#[derive(Parser)]
+#[command(author, version, about = "Duels against the random player", long_about = None)]
+struct Args {
+    /// Portable weights written by `weights export`, they play instead of the Hypergammon database
+    #[arg(long = "weights")]
+    weights: Option<PathBuf>,
+}
 
 fn main() {
-    let probs = duel::<FState<Hypergammon>>(
-        HyperEvaluator::new().unwrap(),
-        // HyperEvaluator::new().unwrap(),
-        // PubEval::new(),
-        // PubEval::new(),
-        RandomEvaluator::new(),
-        // RandomEvaluator::new(),
-        1_000_000,
-    );
+    let args = Args::parse();
+    let probs = match &args.weights {
+        Some(path) => duel::<FState<Hypergammon>>(
+            CpuModel::load(path).expect("Failed to load weights"),
+            RandomEvaluator::new(),
+            1_000_000,
+        ),
+        None => duel::<FState<Hypergammon>>(
+            HyperEvaluator::new().unwrap(),
+            // HyperEvaluator::new().unwrap(),
+            // PubEval::new(),
+            // PubEval::new(),
+            RandomEvaluator::new(),
+            // RandomEvaluator::new(),
+            1_000_000,
+        ),
+    };
 
     println!(
         "Equity: {:.3} ({:.2}%) {:.2},{:.2},{:.2},{:.2},{:.2},{:.2}",
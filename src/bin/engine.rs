@@ -0,0 +1,76 @@
+use std::io::{stdin, stdout};
+use std::path::PathBuf;
+
+use bkgm::{Backgammon, Hypergammon, State};
+use clap::{Parser, ValueEnum};
+use td_gammon::{
+    backend::{device, DefaultBackend},
+    cpu::CpuModel,
+    engine::Engine,
+    evaluator::{HyperEvaluator, PositionEvaluator},
+    fstate::FState,
+    model::{ModelConfig, TDModel},
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Game {
+    Hyper,
+    Backgammon,
+}
+
+#[derive(Parser)]
+#[command(author, version, about = "Engine protocol over stdin/stdout", long_about = None)]
+struct Args {
+    /// Game variant
+    #[arg(short = 'g', long = "game", value_enum, default_value = "hyper")]
+    game: Game,
+
+    /// Model path, uses the Hypergammon database if neither a model nor weights are given
+    #[arg(short = 'm', long = "model")]
+    model_path: Option<PathBuf>,
+
+    /// Portable weights written by `weights export`, evaluated without burn
+    #[arg(long = "weights", conflicts_with = "model_path")]
+    weights: Option<PathBuf>,
+}
+
+fn serve<G: State, E: PositionEvaluator<G>>(evaluator: E) {
+    Engine::<G, E>::new(evaluator)
+        .run(stdin().lock(), stdout().lock())
+        .expect("Failed to communicate over stdin/stdout");
+}
+
+fn run<E>(game: Game, model: Option<E>)
+where
+    E: PositionEvaluator<FState<Hypergammon>> + PositionEvaluator<FState<Backgammon>>,
+{
+    match (game, model) {
+        (Game::Hyper, None) => serve::<Hypergammon, _>(
+            HyperEvaluator::new().expect("Failed to load Hypergammon database"),
+        ),
+        (Game::Hyper, Some(model)) => serve::<FState<Hypergammon>, _>(model),
+        (Game::Backgammon, Some(model)) => serve::<FState<Backgammon>, _>(model),
+        (Game::Backgammon, None) => panic!("Backgammon needs a model, use --model or --weights"),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let config = ModelConfig::new().with_neurons(160).with_nply(1);
+
+    match (&args.model_path, &args.weights) {
+        (Some(path), _) => run(
+            args.game,
+            Some(TDModel::<DefaultBackend>::init_with(
+                config,
+                device(true),
+                path,
+            )),
+        ),
+        (None, Some(path)) => run(
+            args.game,
+            Some(CpuModel::load(path).expect("Failed to load weights")),
+        ),
+        (None, None) => run::<CpuModel>(args.game, None),
+    }
+}
@@ -0,0 +1,85 @@
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+use bkgm::{Backgammon, Hypergammon, State};
+use clap::{Parser, ValueEnum};
+use td_gammon::{
+    backend::{device, DefaultBackend},
+    cpu::CpuModel,
+    evaluator::{HyperEvaluator, PositionEvaluator},
+    external::ExternalPlayer,
+    fstate::FState,
+    model::{ModelConfig, TDModel},
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Game {
+    Hyper,
+    Backgammon,
+}
+
+#[derive(Parser)]
+#[command(author, version, about = "GNU Backgammon external player", long_about = None)]
+struct Args {
+    /// Game variant
+    #[arg(short = 'g', long = "game", value_enum, default_value = "backgammon")]
+    game: Game,
+
+    /// Model path, uses the Hypergammon database if neither a model nor weights are given
+    #[arg(short = 'm', long = "model")]
+    model_path: Option<PathBuf>,
+
+    /// Portable weights written by `weights export`, evaluated without burn
+    #[arg(long = "weights", conflicts_with = "model_path")]
+    weights: Option<PathBuf>,
+
+    /// Port to listen on, use `external localhost:<port>` in gnubg
+    #[arg(short = 'p', long = "port", default_value = "4242")]
+    port: u16,
+}
+
+fn listen<G: State, E: PositionEvaluator<G>>(evaluator: E, port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).expect("Failed to bind port");
+    println!("Listening on 127.0.0.1:{}", port);
+    ExternalPlayer::<G, E>::new(evaluator)
+        .listen(listener)
+        .expect("Connection to gnubg failed");
+}
+
+fn run<E>(game: Game, model: Option<E>, port: u16)
+where
+    E: PositionEvaluator<FState<Hypergammon>> + PositionEvaluator<FState<Backgammon>>,
+{
+    match (game, model) {
+        (Game::Hyper, None) => listen::<Hypergammon, _>(
+            HyperEvaluator::new().expect("Failed to load Hypergammon database"),
+            port,
+        ),
+        (Game::Hyper, Some(model)) => listen::<FState<Hypergammon>, _>(model, port),
+        (Game::Backgammon, Some(model)) => listen::<FState<Backgammon>, _>(model, port),
+        (Game::Backgammon, None) => panic!("Backgammon needs a model, use --model or --weights"),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let config = ModelConfig::new().with_neurons(160).with_nply(1);
+
+    match (&args.model_path, &args.weights) {
+        (Some(path), _) => run(
+            args.game,
+            Some(TDModel::<DefaultBackend>::init_with(
+                config,
+                device(true),
+                path,
+            )),
+            args.port,
+        ),
+        (None, Some(path)) => run(
+            args.game,
+            Some(CpuModel::load(path).expect("Failed to load weights")),
+            args.port,
+        ),
+        (None, None) => run::<CpuModel>(args.game, None, args.port),
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use bkgm::Backgammon;
+use clap::Parser;
+use td_gammon::{
+    backend::{device, DefaultBackend},
+    cpu::CpuModel,
+    evaluator::PositionEvaluator,
+    fibs::{FibsClient, FibsConfig},
+    fstate::FState,
+    model::{ModelConfig, TDModel},
+};
+
+#[derive(Parser)]
+#[command(author, version, about = "Bot for FIBS compatible servers", long_about = None)]
+struct Args {
+    /// Model path
+    #[arg(short = 'm', long = "model", required_unless_present = "weights")]
+    model_path: Option<PathBuf>,
+
+    /// Portable weights written by `weights export`, evaluated without burn
+    #[arg(long = "weights", conflicts_with = "model_path")]
+    weights: Option<PathBuf>,
+
+    /// Server address
+    #[arg(short = 's', long = "server", default_value = "fibs.com:4321")]
+    server: String,
+
+    /// User name of the bot
+    #[arg(short = 'u', long = "user")]
+    user: String,
+
+    /// Password of the bot, read from FIBS_PASSWORD if not given
+    #[arg(short = 'p', long = "password")]
+    password: Option<String>,
+
+    /// Join matches when invited
+    #[arg(short = 'a', long = "accept", default_value = "false")]
+    accept_invitations: bool,
+}
+
+fn connect<E: PositionEvaluator<FState<Backgammon>>>(model: E, server: &str, config: FibsConfig) {
+    FibsClient::<FState<Backgammon>, _>::new(model, config)
+        .connect(server)
+        .expect("Connection to FIBS server failed");
+}
+
+fn main() {
+    let args = Args::parse();
+    let password = args
+        .password
+        .or_else(|| std::env::var("FIBS_PASSWORD").ok())
+        .expect("No password given, use --password or FIBS_PASSWORD");
+
+    let fibs_config = FibsConfig {
+        user: args.user,
+        password,
+        accept_invitations: args.accept_invitations,
+    };
+    match (&args.model_path, &args.weights) {
+        (Some(path), _) => {
+            let config = ModelConfig::new().with_neurons(160).with_nply(1);
+            let model = TDModel::<DefaultBackend>::init_with(config, device(true), path);
+            connect(model, &args.server, fibs_config);
+        }
+        (None, Some(path)) => {
+            let model = CpuModel::load(path).expect("Failed to load weights");
+            connect(model, &args.server, fibs_config);
+        }
+        (None, None) => unreachable!("clap requires a model or weights"),
+    }
+}
@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use bkgm::{Backgammon, Hypergammon, State};
+use clap::{Parser, ValueEnum};
+use td_gammon::backend::{device, DefaultBackend};
+use td_gammon::cpu::CpuModel;
+use td_gammon::evaluator::{Evaluator, HyperEvaluator, PubEval, RandomEvaluator};
+use td_gammon::fstate::FState;
+use td_gammon::generate::{
+    collect_positions, generate, EvaluatorLabeller, GenerateConfig, Labeller, RolloutLabeller,
+};
+use td_gammon::model::{ModelConfig, TDModel};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Game {
+    Hyper,
+    Backgammon,
+}
+
+/// Opponents of the model in the games.
+#[derive(Clone, Copy)]
+enum Against {
+    Random,
+    PubEval,
+    Hyper,
+}
+
+impl FromStr for Against {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "random" => Ok(Against::Random),
+            "pubeval" => Ok(Against::PubEval),
+            "hyper" => Ok(Against::Hyper),
+            _ => Err(format!(
+                "Unknown opponent '{}', expected random, pubeval or hyper",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Parser)]
+#[command(author, version, about = "Writes a dataset of labelled positions", long_about = None)]
+struct Args {
+    /// Game variant
+    #[arg(long = "game", value_enum, default_value = "hyper")]
+    game: Game,
+
+    /// Dataset path, CSV (.csv) or binary (any other extension). An existing dataset is continued
+    #[arg(short = 'o', long = "output")]
+    output: PathBuf,
+
+    /// Model which plays the games and the rollouts, pubeval without it
+    #[arg(short = 'm', long = "model")]
+    model_path: Option<PathBuf>,
+
+    /// Portable weights written by `weights export` instead of a model, evaluated without burn
+    #[arg(long = "weights", conflicts_with = "model_path")]
+    weights: Option<PathBuf>,
+
+    /// Opponent of the model in the games: random, pubeval or hyper (hypergammon only),
+    /// self-play without it
+    #[arg(long = "against")]
+    against: Option<Against>,
+
+    /// Games the positions are collected from
+    #[arg(short = 'g', long = "games", default_value = "1000")]
+    games: usize,
+
+    /// Label by rollouts of this many games, by the hypergammon database without it.
+    /// Backgammon needs rollouts
+    #[arg(short = 'r', long = "rollouts")]
+    rollouts: Option<usize>,
+
+    /// Threads labelling positions
+    #[arg(short = 'w', long = "workers", default_value = "4")]
+    workers: usize,
+
+    /// Seed for the games and rollouts, a resumed run needs the same one
+    #[arg(long = "seed", default_value = "0")]
+    seed: u64,
+
+    /// Use CPU only
+    #[arg(short = 'c', long = "cpu", default_value = "false")]
+    cpu_only: bool,
+}
+
+/// Positions of the games `player` plays against `--against`.
+fn play<G, E>(args: &Args, config: &GenerateConfig, player: &E) -> Vec<G>
+where
+    G: State + Send,
+    E: Evaluator<G>,
+{
+    match args.against {
+        None => collect_positions(player, player, config),
+        Some(Against::Random) => collect_positions(player, &RandomEvaluator::new(), config),
+        Some(Against::PubEval) => collect_positions(player, &PubEval::<G>::new(), config),
+        Some(Against::Hyper) => panic!("The hypergammon database only plays hypergammon"),
+    }
+}
+
+fn label<G: State + Sync, L: Labeller<G>>(
+    args: &Args,
+    config: &GenerateConfig,
+    labeller: &L,
+    positions: &[G],
+) {
+    let progress = |added, todo| {
+        if added == 0 {
+            println!(
+                "{} positions, {} already labelled",
+                positions.len(),
+                positions.len() - todo
+            );
+        } else {
+            println!("{} of {} positions labelled", added, todo);
+        }
+    };
+    let added = generate(config, labeller, positions, &args.output, progress)
+        .expect("Failed to generate the dataset");
+    println!("Added {} positions to {}", added, args.output.display());
+}
+
+fn config(args: &Args) -> GenerateConfig {
+    GenerateConfig::new()
+        .with_games(args.games)
+        .with_workers(args.workers)
+        .with_seed(args.seed)
+}
+
+fn hyper<E: Evaluator<FState<Hypergammon>> + Sync>(args: &Args, player: E) {
+    let config = config(args);
+    let positions: Vec<FState<Hypergammon>> = match args.against {
+        Some(Against::Hyper) => {
+            let hyper = HyperEvaluator::new().expect("Failed to load the hypergammon database");
+            collect_positions(&player, &hyper, &config)
+        }
+        _ => play(args, &config, &player),
+    };
+    match args.rollouts {
+        Some(games) => label(
+            args,
+            &config,
+            &RolloutLabeller::new(player, games),
+            &positions,
+        ),
+        None => {
+            let hyper = HyperEvaluator::new().expect("Failed to load the hypergammon database");
+            label(args, &config, &EvaluatorLabeller(hyper), &positions)
+        }
+    }
+}
+
+fn backgammon<E: Evaluator<FState<Backgammon>> + Sync>(args: &Args, player: E) {
+    let games = args
+        .rollouts
+        .expect("The database only labels hypergammon, use --rollouts for backgammon");
+    let config = config(args);
+    let positions: Vec<FState<Backgammon>> = play(args, &config, &player);
+    label(
+        args,
+        &config,
+        &RolloutLabeller::new(player, games),
+        &positions,
+    );
+}
+
+fn run<E>(args: &Args, model: Option<E>)
+where
+    E: Evaluator<FState<Hypergammon>> + Evaluator<FState<Backgammon>> + Sync,
+{
+    match (args.game, model) {
+        (Game::Hyper, Some(model)) => hyper(args, model),
+        (Game::Hyper, None) => hyper(args, PubEval::new()),
+        (Game::Backgammon, Some(model)) => backgammon(args, model),
+        (Game::Backgammon, None) => backgammon(args, PubEval::new()),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    match (&args.model_path, &args.weights) {
+        (Some(path), _) => {
+            let model = TDModel::<DefaultBackend>::init_with(
+                ModelConfig::new(),
+                device(args.cpu_only),
+                path,
+            );
+            run(&args, Some(model));
+        }
+        (None, Some(path)) => run(
+            &args,
+            Some(CpuModel::load(path).expect("Failed to load weights")),
+        ),
+        (None, None) => run::<CpuModel>(&args, None),
+    }
+}
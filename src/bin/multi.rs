@@ -1,8 +1,8 @@
 use std::path::PathBuf;
 
 use bkgm::Hypergammon;
-use burn::{backend::LibTorch, tensor::backend::Backend};
 use td_gammon::{
+    backend::{device, DefaultBackend},
     duel::duel,
     evaluator::{Evaluator, HyperEvaluator, PubEval, RandomEvaluator},
     fstate::FState,
@@ -10,7 +10,7 @@ use td_gammon::{
     train::TDConfig,
 };
 
-fn run<B: Backend>(config: ModelConfig, td_config: TDConfig) {
+fn run(config: ModelConfig, td_config: TDConfig) {
     // let opponent: Box<dyn Evaluator<FState<Hypergammon>>> = match opponent {
     //     "random" => RandomEvaluator::new(),
     //     "hyper" => HyperEvaluator::new().unwrap(),
@@ -20,7 +20,7 @@ fn run<B: Backend>(config: ModelConfig, td_config: TDConfig) {
 
     let oponent = PubEval::new();
 
-    let device = B::Device::default();
+    let device = device(true);
     println!("round,equity,win,win_n,win_g,win_b,lose_n,lose_g,lose_b");
     let base = format!(
         "model/expr/{}-ply-{}-{}-{}-{}",
@@ -28,12 +28,17 @@ fn run<B: Backend>(config: ModelConfig, td_config: TDConfig) {
         config.layers,
         config.neurons,
         td_config.learning_rate.to_string().replace(".", ""),
-        td_config.td_decay.to_string().replace(".", ""),
+        td_config.lambda.to_string().replace(".", ""),
     );
     for num in 0..=1000 {
         let round = num * 1000;
         let path = PathBuf::from(format!("{}/games-{}.bin", base, round));
-        let eval = TDModel::<B>::init_with(config.clone(), device.clone(), &path);
+        // The directory name only finds the experiment, the network is described by the checkpoint.
+        let eval = match TDModel::<DefaultBackend>::load(&path, &device) {
+            Ok(model) => model,
+            // Experiments from before checkpoints stored their configuration.
+            Err(_) => TDModel::<DefaultBackend>::init_with(config.clone(), device.clone(), &path),
+        };
         let probs = duel::<FState<Hypergammon>>(eval, oponent, 10000);
         println!(
             "{},{:.3},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2},{:.2}",
@@ -52,10 +57,10 @@ fn run<B: Backend>(config: ModelConfig, td_config: TDConfig) {
 
 fn main() {
     let config = ModelConfig::new()
-        // .with_layers(1)
+        .with_layers(1)
         .with_neurons(160)
         .with_nply(1);
 
-    let td_config = TDConfig::new().with_learning_rate(0.1).with_td_decay(0.7);
-    run::<LibTorch>(config, td_config);
+    let td_config = TDConfig::new().with_learning_rate(0.1).with_lambda(0.7);
+    run(config, td_config);
 }
@@ -0,0 +1,262 @@
+use std::io::{stdin, stdout, Write};
+use std::path::PathBuf;
+
+use bkgm::GameState::GameOver;
+use bkgm::{Backgammon, Dice, GameResult, Hypergammon, State};
+use clap::{Parser, ValueEnum};
+use td_gammon::{
+    backend::{device, DefaultBackend},
+    cpu::CpuModel,
+    dicegen::{DiceGen, FastrandDice},
+    display::ascii_board,
+    evaluator::{Evaluator, HyperEvaluator, PositionEvaluator, PubEval, RandomEvaluator},
+    fstate::FState,
+    model::{ModelConfig, TDModel},
+    notation::{find_move, format_moves, moves_between, parse_dice},
+    probabilities::Probabilities,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Game {
+    Hyper,
+    Backgammon,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Opponent {
+    Random,
+    Pubeval,
+    Hyper,
+    Model,
+}
+
+#[derive(Parser)]
+#[command(author, version, about = "Play against a bot in the terminal", long_about = None)]
+struct Args {
+    /// Game variant
+    #[arg(short = 'g', long = "game", value_enum, default_value = "hyper")]
+    game: Game,
+
+    /// Opponent
+    #[arg(short = 'o', long = "opponent", value_enum, default_value = "pubeval")]
+    opponent: Opponent,
+
+    /// Model path, used for the model opponent and for hints
+    #[arg(short = 'm', long = "model")]
+    model_path: Option<PathBuf>,
+
+    /// Portable weights written by `weights export` instead of a model, evaluated without burn
+    #[arg(long = "weights", conflicts_with = "model_path")]
+    weights: Option<PathBuf>,
+
+    /// Enter all dice by hand instead of rolling
+    #[arg(short = 'd', long = "manual-dice", default_value = "false")]
+    manual_dice: bool,
+}
+
+type Bot<G> = Box<dyn Fn(&G, &Dice) -> G>;
+type Hints<G> = Box<dyn Fn(&G, &Dice) -> Vec<(G, Probabilities)>>;
+
+fn bot<G: State, E: Evaluator<G> + 'static>(evaluator: E) -> Bot<G> {
+    Box::new(move |pos, dice| evaluator.best_position(pos, dice))
+}
+
+fn hints<G: State, E: PositionEvaluator<G> + 'static>(evaluator: E) -> Hints<G> {
+    Box::new(move |pos, dice| evaluator.ranked_positions(pos, dice))
+}
+
+const HELP: &str = "Enter moves like '13/8 6/5', 'bar/22*' or '6/2(2)'.
+Other commands: hint, undo, board, help, quit";
+
+fn read_line(prompt: &str) -> Option<String> {
+    print!("{}", prompt);
+    stdout().flush().unwrap();
+    let mut line = String::new();
+    match stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim().to_string()),
+    }
+}
+
+fn next_dice(dice_gen: &mut FastrandDice, manual: bool, first: bool, who: &str) -> Option<Dice> {
+    if !manual {
+        return Some(if first {
+            dice_gen.first_roll()
+        } else {
+            dice_gen.roll()
+        });
+    }
+    loop {
+        let line = read_line(&format!("Dice for {}: ", who))?;
+        match parse_dice(&line) {
+            Ok(dice) => return Some(dice),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+fn show<G: State>(pos: &G, dice: &Dice) {
+    println!("\n{}", ascii_board(&pos.position()));
+    println!("You are X and rolled {:?}", dice);
+}
+
+fn play<G: State>(opponent: Bot<G>, hints: Option<Hints<G>>, manual_dice: bool) {
+    let mut dice_gen = FastrandDice::new();
+    let mut state = G::new();
+    let mut human_turn = fastrand::bool();
+    let mut first = true;
+    // Positions and dice at the start of the human's turns, for undo.
+    let mut history: Vec<(G, Dice)> = Vec::new();
+    println!("{}", HELP);
+
+    'game: loop {
+        if let GameOver(result) = state.game_state() {
+            // The result is from the perspective of the player on roll.
+            let result = if human_turn { result } else { result.reverse() };
+            let message = match result {
+                GameResult::WinNormal => "You win!",
+                GameResult::WinGammon => "You win a gammon!",
+                GameResult::WinBackgammon => "You win a backgammon!",
+                GameResult::LoseNormal => "You lose.",
+                GameResult::LoseGammon => "You lose a gammon.",
+                GameResult::LoseBackgammon => "You lose a backgammon.",
+            };
+            let position = if human_turn {
+                state.position()
+            } else {
+                state.position().flip()
+            };
+            println!("\n{}\n{}", ascii_board(&position), message);
+            return;
+        }
+
+        let who = if human_turn { "you" } else { "the bot" };
+        let mut dice = match next_dice(&mut dice_gen, manual_dice, first, who) {
+            Some(dice) => dice,
+            None => return,
+        };
+        first = false;
+
+        if !human_turn {
+            let next = opponent(&state, &dice);
+            println!(
+                "The bot rolled {:?} and played {}",
+                dice,
+                play_of(&state, &next, &dice)
+            );
+            state = next;
+            human_turn = true;
+            continue;
+        }
+
+        let legal = state.possible_positions(&dice);
+        if legal.len() == 1 && legal[0].position() == state.position().flip() {
+            show(&state, &dice);
+            println!("You can't move.");
+            state = legal[0];
+            human_turn = false;
+            continue;
+        }
+
+        show(&state, &dice);
+        loop {
+            let line = match read_line("Your move: ") {
+                Some(line) => line,
+                None => return,
+            };
+            match line.as_str() {
+                "quit" => return,
+                "help" => println!("{}", HELP),
+                "board" => show(&state, &dice),
+                "hint" => match &hints {
+                    Some(hints) => {
+                        for (pos, probs) in hints(&state, &dice).iter().take(5) {
+                            println!("{:>+7.3}  {}", probs.equity(), play_of(&state, pos, &dice));
+                        }
+                    }
+                    None => println!("No hints available, use --model"),
+                },
+                "undo" => match history.pop() {
+                    Some((previous, previous_dice)) => {
+                        state = previous;
+                        dice = previous_dice;
+                        show(&state, &dice);
+                    }
+                    None => println!("Nothing to undo."),
+                },
+                input => match find_move(&state, &dice, input) {
+                    Ok(next) => {
+                        history.push((state, dice));
+                        state = next;
+                        human_turn = false;
+                        continue 'game;
+                    }
+                    Err(e) => println!("{}", e),
+                },
+            }
+        }
+    }
+}
+
+/// The move from `before` to `after` in standard notation.
+fn play_of<G: State>(before: &G, after: &G, dice: &Dice) -> String {
+    moves_between(&before.position(), &after.position(), dice)
+        .map(|moves| format_moves(&moves))
+        .unwrap_or_default()
+}
+
+fn run<E>(args: &Args, model: Option<E>)
+where
+    E: PositionEvaluator<FState<Hypergammon>>
+        + PositionEvaluator<FState<Backgammon>>
+        + Clone
+        + 'static,
+{
+    match args.game {
+        Game::Hyper => {
+            type G = FState<Hypergammon>;
+            let hyper = || HyperEvaluator::new().expect("Failed to load Hypergammon database");
+            let opponent: Bot<G> = match args.opponent {
+                Opponent::Random => bot(RandomEvaluator::new()),
+                Opponent::Pubeval => bot(PubEval::<G>::new()),
+                Opponent::Hyper => bot(hyper()),
+                Opponent::Model => bot(model
+                    .clone()
+                    .expect("Use --model or --weights for the model")),
+            };
+            let hint: Hints<G> = match model {
+                Some(model) => hints(model),
+                None => hints(hyper()),
+            };
+            play(opponent, Some(hint), args.manual_dice);
+        }
+        Game::Backgammon => {
+            type G = FState<Backgammon>;
+            let opponent: Bot<G> = match args.opponent {
+                Opponent::Random => bot(RandomEvaluator::new()),
+                Opponent::Pubeval => bot(PubEval::<G>::new()),
+                Opponent::Hyper => panic!("The Hypergammon database only plays Hypergammon"),
+                Opponent::Model => bot(model
+                    .clone()
+                    .expect("Use --model or --weights for the model")),
+            };
+            play(opponent, model.map(hints), args.manual_dice);
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    match (&args.model_path, &args.weights) {
+        (Some(path), _) => {
+            let config = ModelConfig::new().with_neurons(160).with_nply(1);
+            let model = TDModel::<DefaultBackend>::init_with(config, device(true), path);
+            run(&args, Some(model));
+        }
+        (None, Some(path)) => run(
+            &args,
+            Some(CpuModel::load(path).expect("Failed to load weights")),
+        ),
+        (None, None) => run::<CpuModel>(&args, None),
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use bkgm::{Backgammon, Hypergammon};
+use clap::{Parser, ValueEnum};
+use td_gammon::{
+    backend::{device, DefaultBackend},
+    cpu::CpuModel,
+    evaluator::PositionEvaluator,
+    fstate::FState,
+    model::{ModelConfig, TDModel},
+    server::{serve, ServerConfig},
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Game {
+    Hyper,
+    Backgammon,
+}
+
+#[derive(Parser)]
+#[command(author, version, about = "HTTP/JSON evaluation service", long_about = None)]
+struct Args {
+    /// Model path
+    #[arg(short = 'm', long = "model", required_unless_present = "weights")]
+    model_path: Option<PathBuf>,
+
+    /// Portable weights written by `weights export`, evaluated without burn
+    #[arg(long = "weights", conflicts_with = "model_path")]
+    weights: Option<PathBuf>,
+
+    /// Game variant
+    #[arg(short = 'g', long = "game", value_enum, default_value = "backgammon")]
+    game: Game,
+
+    /// Address to listen on
+    #[arg(short = 'a', long = "address", default_value = "127.0.0.1:8080")]
+    address: String,
+
+    /// Number of threads answering requests
+    #[arg(short = 't', long = "threads", default_value = "8")]
+    threads: usize,
+
+    /// Number of threads playing rollouts
+    #[arg(short = 'r', long = "rollout-threads", default_value = "4")]
+    rollout_threads: usize,
+
+    /// Use CPU only
+    #[arg(short = 'c', long = "cpu", default_value = "false")]
+    cpu_only: bool,
+}
+
+fn run<E>(args: &Args, model: E)
+where
+    E: PositionEvaluator<FState<Hypergammon>>
+        + PositionEvaluator<FState<Backgammon>>
+        + Send
+        + 'static,
+{
+    let server_config = ServerConfig {
+        threads: args.threads,
+        rollout_threads: args.rollout_threads,
+        ..ServerConfig::default()
+    };
+
+    println!("Listening on {}", args.address);
+    match args.game {
+        Game::Hyper => serve::<FState<Hypergammon>, _>(model, &args.address, server_config),
+        Game::Backgammon => serve::<FState<Backgammon>, _>(model, &args.address, server_config),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    match (&args.model_path, &args.weights) {
+        (Some(path), _) => {
+            let config = ModelConfig::new().with_neurons(160).with_nply(1);
+            let device = device(args.cpu_only);
+            run(
+                &args,
+                TDModel::<DefaultBackend>::init_with(config, device, path),
+            );
+        }
+        (None, Some(path)) => run(&args, CpuModel::load(path).expect("Failed to load weights")),
+        (None, None) => unreachable!("clap requires a model or weights"),
+    }
+}
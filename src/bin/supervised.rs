@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use td_gammon::backend::{device, TrainingBackend};
+use td_gammon::inputs::Encoding;
+use td_gammon::model::{Activation, ModelConfig, TDModel, TDNetwork};
+use td_gammon::supervised::{SupervisedConfig, SupervisedTrainer};
+
+#[derive(Parser)]
+#[command(author, version, about = "Trains a network on labelled positions", long_about = None)]
+struct Args {
+    /// Dataset files, CSV (.csv) or binary (any other extension)
+    #[arg(short = 'i', long = "input", value_delimiter = ',', required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Checkpoint to start from, a new network without it
+    #[arg(short = 'm', long = "model")]
+    model_path: Option<PathBuf>,
+
+    /// Where the trained network is saved
+    #[arg(short = 'o', long = "output")]
+    output: PathBuf,
+
+    /// Directory for the losses of every epoch and the best network so far
+    #[arg(short = 'd', long = "dir")]
+    dir: Option<PathBuf>,
+
+    /// Use CPU only
+    #[arg(short = 'c', long = "cpu", default_value = "false")]
+    cpu_only: bool,
+
+    /// Number of hidden layers
+    #[arg(short = 'l', long = "layers", default_value = "1")]
+    layers: usize,
+
+    /// Neurons per hidden layer
+    #[arg(short = 'n', long = "neurons", default_value = "160")]
+    neurons: usize,
+
+    /// Activation of the hidden layers: sigmoid, relu or tanh
+    #[arg(short = 'a', long = "activation", default_value = "sigmoid")]
+    activation: Activation,
+
+    /// Input encoding: standard, tesauro or extended
+    #[arg(short = 'e', long = "encoding", default_value = "standard")]
+    encoding: Encoding,
+
+    #[arg(long = "learning-rate", default_value = "0.001")]
+    learning_rate: f64,
+
+    #[arg(long = "batch-size", default_value = "128")]
+    batch_size: usize,
+
+    #[arg(long = "epochs", default_value = "100")]
+    epochs: usize,
+
+    /// Share of the samples held out for validation
+    #[arg(long = "validation", default_value = "0.1")]
+    validation: f64,
+
+    /// Epochs without a lower validation loss before training stops, 0 never stops early
+    #[arg(long = "patience", default_value = "5")]
+    patience: usize,
+}
+
+fn main() {
+    let args = Args::parse();
+    let device = device(args.cpu_only);
+
+    let model = match &args.model_path {
+        Some(path) => {
+            TDModel::<TrainingBackend>::load(path, &device).expect("Failed to load model")
+        }
+        None => {
+            let config = ModelConfig::new()
+                .with_layers(args.layers)
+                .with_neurons(args.neurons)
+                .with_activation(args.activation)
+                .with_encoding(args.encoding);
+            TDModel::<TrainingBackend>::new(config, &device)
+        }
+    };
+
+    let config = SupervisedConfig::new()
+        .with_learning_rate(args.learning_rate)
+        .with_batch_size(args.batch_size)
+        .with_max_epochs(args.epochs)
+        .with_validation(args.validation)
+        .with_patience(args.patience);
+    let trainer = SupervisedTrainer::<TrainingBackend>::new(device, config);
+    let model = trainer
+        .train(model, &args.inputs, args.dir.clone())
+        .expect("Failed to train");
+
+    let info = model.checkpoint_info();
+    model.save(args.output, info).expect("Failed to save model");
+}
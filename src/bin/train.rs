@@ -1,6 +1,5 @@
 use std::path::PathBuf;
 
-use burn::module::Module;
 use clap::Parser;
 
 #[derive(Parser)]
@@ -14,58 +13,173 @@ pub struct Args {
     #[arg(short = 'd', long = "dir")]
     dir: Option<PathBuf>,
 
-    /// Verbose
-    #[arg(short = 'v', long = "verbose", default_value = "false")]
-    verbose: bool,
-
     /// Use CPU only
     #[arg(short = 'c', long = "cpu", default_value = "false")]
     cpu_only: bool,
+
+    /// Number of hidden layers
+    #[arg(short = 'l', long = "layers", default_value = "1")]
+    layers: usize,
+
+    /// Neurons per hidden layer
+    #[arg(short = 'n', long = "neurons", default_value = "160")]
+    neurons: usize,
+
+    /// Activation of the hidden layers: sigmoid, relu or tanh
+    #[arg(short = 'a', long = "activation", default_value = "sigmoid")]
+    activation: Activation,
+
+    /// Input encoding: standard, tesauro or extended
+    #[arg(short = 'e', long = "encoding", default_value = "standard")]
+    encoding: Encoding,
+
+    /// Separate nets for contact and race positions
+    #[arg(long = "multi", default_value = "false")]
+    multi: bool,
+
+    /// Threads playing self-play games, more than one trains from snapshots of the model
+    #[arg(short = 'w', long = "workers", default_value = "1")]
+    workers: usize,
+
+    /// Seed for the dice of every game, makes a run repeatable
+    #[arg(long = "seed")]
+    seed: Option<u64>,
+
+    /// Episodes between benchmarks, none without it
+    #[arg(short = 'b', long = "benchmark")]
+    benchmark: Option<usize>,
+
+    /// Benchmark opponents: random, pubeval, hyper and best
+    #[arg(
+        long = "opponents",
+        value_delimiter = ',',
+        default_value = "random,pubeval,best"
+    )]
+    opponents: Vec<Opponent>,
+
+    /// Format of the metrics files: csv or jsonl
+    #[arg(long = "metrics-format", default_value = "csv")]
+    metrics_format: MetricsFormat,
+
+    /// Checkpoint of an earlier run to continue, its network and training configuration are used
+    #[arg(short = 'r', long = "resume")]
+    resume: Option<PathBuf>,
+
+    /// Trains against opponents instead of self-play: pubeval, random or checkpoints,
+    /// a directory stands for all the checkpoints in it
+    #[arg(long = "against", value_delimiter = ',')]
+    against: Vec<String>,
+
+    /// Exploration of training games: greedy, or epsilon, softmax or noise with a value or a
+    /// start:end:episodes schedule, e.g. softmax=1:0.1:100000
+    #[arg(long = "explore", default_value = "greedy")]
+    exploration: Exploration,
+
+    /// Where training games start: opening, file=path with one Position ID or XGID per line,
+    /// random=max_moves or curriculum=episodes, bearoffs first
+    #[arg(long = "start", default_value = "opening")]
+    start: StartPositions,
 }
 
-use bkgm::{Backgammon, Hypergammon};
-use burn::backend::libtorch::{LibTorch, LibTorchDevice};
-use burn::backend::Autodiff;
-use burn::record::NoStdTrainingRecorder;
-use td_gammon::model::{ModelConfig, TDModel};
+use bkgm::Hypergammon;
+use burn::module::AutodiffModule;
+use td_gammon::backend::{device, DefaultBackend, TrainingBackend};
+use td_gammon::benchmark::{BenchmarkConfig, Opponent};
+use td_gammon::evaluator::{PositionEvaluator, PubEval, RandomEvaluator};
+use td_gammon::exploration::Exploration;
+use td_gammon::fstate::FState;
+use td_gammon::inputs::Encoding;
+use td_gammon::league::{checkpoints, League};
+use td_gammon::metrics::MetricsFormat;
+use td_gammon::model::{Activation, ModelConfig, TDModel, TDNetwork};
+use td_gammon::multinet::MultiNet;
+use td_gammon::start::StartPositions;
 use td_gammon::train::{TDConfig, TDTrainer};
 
-fn get_device(cup_only: bool) -> LibTorchDevice {
-    if cup_only {
-        LibTorchDevice::Cpu
-    } else {
-        #[cfg(not(target_os = "macos"))]
-        let device = LibTorchDevice::Cuda(0);
-        // MacOs Mps too slow
-        #[cfg(target_os = "macos")]
-        let device = LibTorchDevice::Cpu;
-        // let device = LibTorchDevice::Mps;
-        device
+const EPISODES: usize = 500_000;
+
+/// Self-play, or games against `args.against`.
+fn train<M>(td: &mut TDTrainer<TrainingBackend>, args: &Args, model: M) -> M
+where
+    M: TDNetwork<TrainingBackend>
+        + AutodiffModule<TrainingBackend>
+        + PositionEvaluator<FState<Hypergammon>>,
+    M::InnerModule: PositionEvaluator<FState<Hypergammon>> + Send,
+{
+    let dir = args.dir.clone();
+    match args.against.as_slice() {
+        [] if args.workers > 1 => td.train_parallel::<Hypergammon, _>(dir, model, EPISODES),
+        [] => td.train::<Hypergammon, _>(dir, model, EPISODES),
+        [name] if name == "pubeval" => {
+            let league = League::single(PubEval::<FState<Hypergammon>>::new());
+            td.train_against::<Hypergammon, _, _>(dir, model, &league, EPISODES)
+        }
+        [name] if name == "random" => {
+            let league = League::single(RandomEvaluator::new());
+            td.train_against::<Hypergammon, _, _>(dir, model, &league, EPISODES)
+        }
+        paths => {
+            let mut members = Vec::new();
+            for path in paths.iter().map(PathBuf::from) {
+                if path.is_dir() {
+                    members.extend(checkpoints(&path).expect("Failed to list checkpoints"));
+                } else {
+                    members.push(path);
+                }
+            }
+            let league = League::<TDModel<DefaultBackend>>::load(&members, &device(args.cpu_only))
+                .expect("Failed to load the league");
+            td.train_against::<Hypergammon, _, _>(dir, model, &league, EPISODES)
+        }
     }
 }
 
 pub fn run(args: &Args) {
-    let device = get_device(args.cpu_only);
+    let device = device(args.cpu_only);
 
     let config = ModelConfig::new()
-        // .with_layers(1)
-        .with_neurons(160)
+        .with_layers(args.layers)
+        .with_neurons(args.neurons)
+        .with_activation(args.activation)
+        .with_encoding(args.encoding)
         .with_nply(1);
 
-    let td_config = TDConfig::new().with_learning_rate(0.1).with_td_decay(0.7);
+    let td_config = TDConfig::new()
+        .with_learning_rate(0.1)
+        .with_lambda(0.7)
+        .with_workers(args.workers)
+        .with_seed(args.seed)
+        .with_metrics_format(args.metrics_format)
+        .with_exploration(args.exploration.clone())
+        .with_start(args.start.clone())
+        .with_benchmark(args.benchmark.map(|interval| {
+            BenchmarkConfig::new()
+                .with_interval(interval)
+                .with_opponents(args.opponents.clone())
+        }));
+    td_config
+        .validate::<Hypergammon>()
+        .expect("Invalid training configuration");
 
-    let model = match &args.model_path {
-        Some(path) => TDModel::<Autodiff<LibTorch>>::init_with(config, device, path),
-        None => TDModel::<Autodiff<LibTorch>>::new(config, &device),
-    };
+    let mut td: TDTrainer<TrainingBackend> = TDTrainer::new(device.clone(), td_config);
 
-    let mut td: TDTrainer<Autodiff<LibTorch>> = TDTrainer::new(device.clone(), td_config);
+    if args.multi {
+        let model = match (&args.resume, &args.model_path) {
+            (Some(path), _) => td.resume(path).expect("Failed to resume training"),
+            (None, Some(path)) => MultiNet::<TrainingBackend>::init_with(config, device, path),
+            (None, None) => MultiNet::<TrainingBackend>::new(config, &device),
+        };
+        train(&mut td, args, model);
+        return;
+    }
 
-    let model = td.train::<Hypergammon>(args.dir.clone(), model, 500_000);
+    let model = match (&args.resume, &args.model_path) {
+        (Some(path), _) => td.resume(path).expect("Failed to resume training"),
+        (None, Some(path)) => TDModel::<TrainingBackend>::init_with(config, device, path),
+        (None, None) => TDModel::<TrainingBackend>::new(config, &device),
+    };
 
-    // model
-    //     .save_file(format!("model/td-next"), &NoStdTrainingRecorder::new())
-    //     .expect("Failed to save trained model");
+    train(&mut td, args, model);
 }
 
 fn main() {
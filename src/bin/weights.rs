@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use td_gammon::{
+    backend::{device, DefaultBackend},
+    model::{ModelConfig, TDModel, TDNetwork},
+    weights::Weights,
+};
+
+#[derive(Parser)]
+#[command(author, version, about = "Converts checkpoints to portable weights and back", long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Writes the weights of a checkpoint as JSON (.json) or binary (any other extension)
+    Export {
+        /// Checkpoint path
+        #[arg(short = 'm', long = "model")]
+        model_path: PathBuf,
+
+        /// Weights path
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+    },
+    /// Turns JSON or binary weights into a checkpoint
+    Import {
+        /// Weights path
+        #[arg(short = 'i', long = "input")]
+        input: PathBuf,
+
+        /// Checkpoint path
+        #[arg(short = 'm', long = "model")]
+        model_path: PathBuf,
+    },
+}
+
+fn main() {
+    let args = Args::parse();
+    let device = device(true);
+    match args.command {
+        Command::Export { model_path, output } => {
+            let model =
+                TDModel::<DefaultBackend>::init_with(ModelConfig::new(), device, &model_path);
+            Weights::from_model(&model)
+                .save(&output)
+                .expect("Failed to export weights");
+        }
+        Command::Import { input, model_path } => {
+            let model = Weights::load(&input)
+                .and_then(|weights| weights.to_model::<DefaultBackend>(&device))
+                .expect("Failed to import weights");
+            let info = model.checkpoint_info();
+            model.save(model_path, info).expect("Failed to save model");
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use crate::model::ModelConfig;
+use crate::train::TDConfig;
+use burn::config::Config;
+
+/// Increased whenever the layout of checkpoints changes.
+pub const CHECKPOINT_VERSION: usize = 1;
+
+/// Embedded as a JSON string in the checkpoint record, the `info` of `TDModelFile`, so that a
+/// checkpoint can be loaded without knowing how it was created.
+#[derive(Config)]
+pub struct CheckpointInfo {
+    /// `TDNetwork::KIND` of the saved network.
+    pub kind: String,
+    pub model: ModelConfig,
+    /// Hyperparameters of the training run which produced the checkpoint.
+    #[config(default = "None")]
+    pub training: Option<TDConfig>,
+    /// Number of training games played so far.
+    #[config(default = 0)]
+    pub episode: usize,
+    #[config(default = "CHECKPOINT_VERSION")]
+    pub version: usize,
+}
+
+impl CheckpointInfo {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize checkpoint info")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid checkpoint info: {}", e))
+    }
+
+    /// Fails for checkpoints of a different network type or a newer version of this crate.
+    pub fn check(&self, kind: &str) -> Result<(), String> {
+        if self.version > CHECKPOINT_VERSION {
+            return Err(format!(
+                "Checkpoint version {} is newer than the supported version {}",
+                self.version, CHECKPOINT_VERSION
+            ));
+        }
+        if self.kind != kind {
+            return Err(format!(
+                "Checkpoint holds a {} network, not a {} network",
+                self.kind, kind
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checkpoint::{CheckpointInfo, CHECKPOINT_VERSION};
+    use crate::inputs::Encoding;
+    use crate::model::{Activation, ModelConfig};
+    use crate::train::TDConfig;
+
+    #[test]
+    fn json_round_trip() {
+        let model = ModelConfig::new()
+            .with_widths(vec![80, 40])
+            .with_activation(Activation::Relu)
+            .with_encoding(Encoding::Tesauro);
+        let info = CheckpointInfo::new("td".to_string(), model)
+            .with_training(Some(TDConfig::new().with_learning_rate(0.05)))
+            .with_episode(12_000);
+
+        let loaded = CheckpointInfo::from_json(&info.to_json()).unwrap();
+        assert_eq!(loaded.version, CHECKPOINT_VERSION);
+        assert_eq!(loaded.episode, 12_000);
+        assert_eq!(loaded.model.hidden_widths(), vec![80, 40]);
+        assert_eq!(loaded.model.activation, Activation::Relu);
+        assert_eq!(loaded.model.encoding, Encoding::Tesauro);
+        assert_eq!(loaded.training.unwrap().learning_rate, 0.05);
+    }
+
+    #[test]
+    fn check() {
+        let info = CheckpointInfo::new("multi".to_string(), ModelConfig::new());
+        assert!(info.check("multi").is_ok());
+        assert_eq!(
+            info.check("td"),
+            Err("Checkpoint holds a multi network, not a td network".to_string())
+        );
+        let info = info.with_version(CHECKPOINT_VERSION + 1);
+        assert!(info.check("multi").is_err());
+        assert!(CheckpointInfo::from_json("standard").is_err());
+    }
+}
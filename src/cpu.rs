@@ -0,0 +1,257 @@
+use crate::evaluator::{Evaluator, PositionEvaluator};
+use crate::inputs::{Encoder, Encoding};
+use crate::model::{probabilities_from_outputs, Activation, TDModel};
+use crate::search::{self, BatchEvaluator};
+use crate::weights::Weights;
+use crate::{fstate::FState, probabilities::Probabilities};
+use bkgm::{Dice, State};
+use burn::tensor::backend::Backend;
+use std::path::Path;
+
+/// A dense layer in plain memory.
+/// The weights are stored by output, so that every output is a dot product of two slices.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    inputs: usize,
+    outputs: usize,
+    weights: Vec<f32>,
+    bias: Vec<f32>,
+}
+
+impl Layer {
+    /// `weights` is laid out like burn's `Linear`, `inputs` rows of `outputs` values.
+    pub fn new(inputs: usize, outputs: usize, weights: &[f32], bias: &[f32]) -> Self {
+        assert_eq!(weights.len(), inputs * outputs, "Wrong number of weights");
+        assert_eq!(bias.len(), outputs, "Wrong number of biases");
+        let mut transposed = vec![0.0; inputs * outputs];
+        for i in 0..inputs {
+            for o in 0..outputs {
+                transposed[o * inputs + i] = weights[i * outputs + o];
+            }
+        }
+        Self {
+            inputs,
+            outputs,
+            weights: transposed,
+            bias: bias.to_vec(),
+        }
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    fn forward(&self, input: &[f32], output: &mut [f32]) {
+        for ((out, weights), bias) in output
+            .iter_mut()
+            .zip(self.weights.chunks_exact(self.inputs))
+            .zip(&self.bias)
+        {
+            *out = dot(input, weights) + bias;
+        }
+    }
+}
+
+/// Eight independent sums, so that the compiler can use SIMD instructions.
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let mut sums = [0.0; 8];
+    let mut a_chunks = a.chunks_exact(8);
+    let mut b_chunks = b.chunks_exact(8);
+    for (a, b) in (&mut a_chunks).zip(&mut b_chunks) {
+        for k in 0..8 {
+            sums[k] += a[k] * b[k];
+        }
+    }
+    let rest: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(a, b)| a * b)
+        .sum();
+    sums.iter().sum::<f32>() + rest
+}
+
+fn activate(activation: Activation, values: &mut [f32]) {
+    for x in values {
+        *x = match activation {
+            Activation::Sigmoid => sigmoid(*x),
+            Activation::Relu => x.max(0.0),
+            Activation::Tanh => x.tanh(),
+        }
+    }
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Inference only copy of a `TDModel`, which needs no burn backend to evaluate positions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuModel {
+    /// Hidden layers followed by the output layer.
+    layers: Vec<Layer>,
+    activation: Activation,
+    encoding: Encoding,
+}
+
+impl CpuModel {
+    pub fn new(layers: Vec<Layer>, activation: Activation, encoding: Encoding) -> Self {
+        assert!(
+            layers.len() >= 2,
+            "Needs at least one hidden and one output layer"
+        );
+        assert_eq!(
+            layers[0].inputs,
+            encoding.num_inputs(),
+            "Wrong number of inputs"
+        );
+        for pair in layers.windows(2) {
+            assert_eq!(pair[0].outputs, pair[1].inputs, "Layer sizes don't match");
+        }
+        Self {
+            layers,
+            activation,
+            encoding,
+        }
+    }
+
+    pub fn from_model<B: Backend>(model: &TDModel<B>) -> Self {
+        Weights::from_model(model).to_cpu().expect("Invalid model")
+    }
+
+    /// Weights saved by `Weights::save`, JSON or binary. Tools use this to play without burn.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Weights::load(path)?.to_cpu()
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn activation(&self) -> Activation {
+        self.activation
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Raw outputs of the network for one position.
+    pub fn forward(&self, inputs: &[f32]) -> Vec<f32> {
+        let mut values = inputs.to_vec();
+        let last = self.layers.len() - 1;
+        for (i, layer) in self.layers.iter().enumerate() {
+            let mut next = vec![0.0; layer.outputs];
+            layer.forward(&values, &mut next);
+            if i == last {
+                activate(Activation::Sigmoid, &mut next);
+            } else {
+                activate(self.activation, &mut next);
+            }
+            values = next;
+        }
+        values
+    }
+}
+
+impl BatchEvaluator for CpuModel {
+    fn evaluate<G: State>(&self, positions: &[G]) -> Vec<Probabilities> {
+        let mut inputs = vec![0.0; self.encoding.num_inputs()];
+        positions
+            .iter()
+            .map(|pos| {
+                self.encoding.encode_into(&pos.position(), &mut inputs);
+                probabilities_from_outputs(&self.forward(&inputs))
+            })
+            .collect()
+    }
+}
+
+impl<G: State + Send> Evaluator<FState<G>> for CpuModel {
+    fn best_position(&self, pos: &FState<G>, dice: &Dice) -> FState<G> {
+        search::best_position(self, 1, pos, dice)
+    }
+}
+
+impl<G: State + Send> PositionEvaluator<FState<G>> for CpuModel {
+    fn eval(&self, pos: &FState<G>) -> Probabilities {
+        self.evaluate(&[*pos])[0]
+    }
+
+    fn eval_batch(&self, positions: &[FState<G>]) -> Vec<Probabilities> {
+        self.evaluate(positions)
+    }
+
+    /// The same ranking as `best_position`, see `search::ranked_positions`.
+    fn ranked_positions(&self, pos: &FState<G>, dice: &Dice) -> Vec<(FState<G>, Probabilities)> {
+        search::ranked_positions(self, pos, dice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{device, DefaultBackend};
+    use crate::cpu::{dot, CpuModel, Layer};
+    use crate::inputs::Encoding;
+    use crate::model::{Activation, ModelConfig, TDModel};
+    use crate::search::BatchEvaluator;
+    use crate::test_dir::TestDir;
+    use crate::weights::Weights;
+    use bkgm::{pos, Backgammon, State};
+
+    #[test]
+    fn same_probabilities_as_model() {
+        let positions = [
+            Backgammon::new(),
+            Backgammon::from_position(pos!(x 25:1, 6:5, 1:3; o 0:2, 12:4, 24:1)),
+        ];
+        for encoding in Encoding::ALL {
+            let config = ModelConfig::new()
+                .with_widths(vec![16, 8])
+                .with_activation(Activation::Tanh)
+                .with_encoding(encoding);
+            let model = TDModel::<DefaultBackend>::new(config, &device(true));
+            let cpu = CpuModel::from_model(&model);
+            let expected = model.evaluate(&positions);
+            let actual = cpu.evaluate(&positions);
+            for (expected, actual) in expected.iter().zip(actual) {
+                for (e, a) in expected.to_slice().iter().zip(actual.to_slice()) {
+                    assert!((e - a).abs() < 1e-5, "{:?} != {:?}", expected, actual);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn load_weights() {
+        let model =
+            TDModel::<DefaultBackend>::new(ModelConfig::new().with_neurons(8), &device(true));
+        let dir = TestDir::new("cpu-weights");
+        for name in ["weights.json", "weights.bin"] {
+            let path = dir.join(name);
+            Weights::from_model(&model).save(&path).unwrap();
+            assert_eq!(CpuModel::load(&path).unwrap(), CpuModel::from_model(&model));
+        }
+        assert!(CpuModel::load(&dir.join("missing.bin")).is_err());
+    }
+
+    #[test]
+    fn dot_with_remainder() {
+        let a: Vec<f32> = (1..=11).map(|i| i as f32).collect();
+        let b = vec![2.0; 11];
+        assert_eq!(dot(&a, &b), 132.0);
+    }
+
+    #[test]
+    fn layer_uses_burn_layout() {
+        // 3 inputs, 2 outputs: row i holds the weights from input i
+        let layer = Layer::new(3, 2, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[0.5, -0.5]);
+        let mut output = [0.0; 2];
+        layer.forward(&[1.0, 0.0, 2.0], &mut output);
+        assert_eq!(output, [1.0 + 10.0 + 0.5, 2.0 + 12.0 - 0.5]);
+    }
+}
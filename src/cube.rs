@@ -0,0 +1,178 @@
+use std::fmt;
+
+use crate::probabilities::Probabilities;
+
+/// Cube efficiency used to interpolate between dead and live cube equities.
+/// 0.68 is the value GNU Backgammon uses for contact positions.
+pub const CUBE_EFFICIENCY: f32 = 0.68;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeAction {
+    NoDouble,
+    DoubleTake,
+    DoublePass,
+    TooGood,
+}
+
+impl fmt::Display for CubeAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self {
+            CubeAction::NoDouble => "no-double",
+            CubeAction::DoubleTake => "double-take",
+            CubeAction::DoublePass => "double-pass",
+            CubeAction::TooGood => "too-good",
+        };
+        write!(f, "{}", action)
+    }
+}
+
+/// Money game cube decision for a centered cube, from the perspective of the player on roll.
+/// All equities are normalized to a cube value of 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CubeDecision {
+    pub no_double: f32,
+    pub double_take: f32,
+    pub double_pass: f32,
+}
+
+impl CubeDecision {
+    /// Uses Janowski's model to turn cubeless probabilities into cubeful equities.
+    pub fn new(probs: &Probabilities) -> Self {
+        Self::with_efficiency(probs, CUBE_EFFICIENCY)
+    }
+
+    pub fn with_efficiency(probs: &Probabilities, x: f32) -> Self {
+        let janowski = Janowski::new(probs);
+        let dead = probs.equity();
+        Self {
+            no_double: x * janowski.centered() + (1.0 - x) * dead,
+            double_take: 2.0 * (x * janowski.opponent_owns() + (1.0 - x) * dead),
+            double_pass: 1.0,
+        }
+    }
+
+    pub fn action(&self) -> CubeAction {
+        if self.double_take >= self.double_pass {
+            if self.no_double >= self.double_pass {
+                CubeAction::TooGood
+            } else {
+                CubeAction::DoublePass
+            }
+        } else if self.double_take > self.no_double {
+            CubeAction::DoubleTake
+        } else {
+            CubeAction::NoDouble
+        }
+    }
+
+    /// Whether the opponent of the player on roll should take a double.
+    pub fn should_take(&self) -> bool {
+        self.double_take < self.double_pass
+    }
+}
+
+struct Janowski {
+    p: f32,
+    /// Average value of a win
+    w: f32,
+    /// Average value of a loss
+    l: f32,
+}
+
+impl Janowski {
+    fn new(probs: &Probabilities) -> Self {
+        let win = probs.win_prob();
+        let lose = probs.lose_n + probs.lose_g + probs.lose_b;
+        let w = if win > 0.0 {
+            (probs.win_n + 2.0 * probs.win_g + 3.0 * probs.win_b) / win
+        } else {
+            1.0
+        };
+        let l = if lose > 0.0 {
+            (probs.lose_n + 2.0 * probs.lose_g + 3.0 * probs.lose_b) / lose
+        } else {
+            1.0
+        };
+        Self { p: win, w, l }
+    }
+
+    fn take_point(&self) -> f32 {
+        (self.l - 0.5) / (self.w + self.l + 0.5)
+    }
+
+    fn cash_point(&self) -> f32 {
+        (self.l + 1.0) / (self.w + self.l + 0.5)
+    }
+
+    fn centered(&self) -> f32 {
+        let (tp, cp) = (self.take_point(), self.cash_point());
+        if self.p < tp {
+            -self.l + (self.l - 1.0) * self.p / tp
+        } else if self.p < cp {
+            -1.0 + 2.0 * (self.p - tp) / (cp - tp)
+        } else {
+            1.0 + (self.w - 1.0) * (self.p - cp) / (1.0 - cp)
+        }
+    }
+
+    fn opponent_owns(&self) -> f32 {
+        let tp = self.take_point();
+        if self.p < tp {
+            -self.l + (self.l - 1.0) * self.p / tp
+        } else {
+            -1.0 + (self.w + 1.0) * (self.p - tp) / (1.0 - tp)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cube::{CubeAction, CubeDecision};
+    use crate::probabilities::Probabilities;
+
+    fn probs(win: f32) -> Probabilities {
+        Probabilities {
+            win_n: win,
+            win_g: 0.0,
+            win_b: 0.0,
+            lose_n: 1.0 - win,
+            lose_g: 0.0,
+            lose_b: 0.0,
+        }
+    }
+
+    #[test]
+    fn dead_cube_take_point_is_25_percent() {
+        assert!(CubeDecision::with_efficiency(&probs(0.74), 0.0).should_take());
+        assert!(!CubeDecision::with_efficiency(&probs(0.76), 0.0).should_take());
+    }
+
+    #[test]
+    fn actions() {
+        assert_eq!(
+            CubeDecision::new(&probs(0.5)).action(),
+            CubeAction::NoDouble
+        );
+        assert_eq!(
+            CubeDecision::new(&probs(0.72)).action(),
+            CubeAction::DoubleTake
+        );
+        assert_eq!(
+            CubeDecision::new(&probs(0.85)).action(),
+            CubeAction::DoublePass
+        );
+    }
+
+    #[test]
+    fn gammonish_position_is_too_good() {
+        let probs = Probabilities {
+            win_n: 0.2,
+            win_g: 0.75,
+            win_b: 0.0,
+            lose_n: 0.05,
+            lose_g: 0.0,
+            lose_b: 0.0,
+        };
+        assert_eq!(CubeDecision::new(&probs).action(), CubeAction::TooGood);
+    }
+}
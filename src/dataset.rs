@@ -0,0 +1,338 @@
+//! Labelled positions for supervised training, as CSV or binary files.
+//!
+//! CSV files are semicolon separated with a header. The columns are `position_id`, the five
+//! cumulative probabilities `win;win_g;win_b;lose_g;lose_b` from the perspective of the player
+//! on roll, then the `Inputs` of the position, see `Inputs::csv_header`. Only the first six
+//! columns are read, the inputs are there for other tools.
+//!
+//! Binary files are little endian:
+//!
+//! ```text
+//! "TDGD"  magic
+//! u32     version, 1
+//! then per sample:
+//! [u8;14] GNU Backgammon Position ID
+//! [f32;5] win, win_g, win_b, lose_g, lose_b
+//! ```
+
+use crate::inputs::Inputs;
+use crate::notation::parse_position_id;
+use crate::probabilities::Probabilities;
+use bkgm::Position;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"TDGD";
+const VERSION: u32 = 1;
+const ID_LENGTH: usize = 14;
+const RECORD_LENGTH: u64 = ID_LENGTH as u64 + 5 * 4;
+const COLUMNS: [&str; 6] = ["position_id", "win", "win_g", "win_b", "lose_g", "lose_b"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub position: Position,
+    /// From the perspective of the player on roll.
+    pub probabilities: Probabilities,
+}
+
+/// Files ending with `.csv` are CSV, all others binary.
+fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "csv")
+}
+
+pub enum DatasetWriter {
+    Csv(csv::Writer<File>),
+    Binary(BufWriter<File>),
+}
+
+impl DatasetWriter {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        if is_csv(path) {
+            let mut writer = csv::WriterBuilder::new().delimiter(b';').from_writer(file);
+            let mut header: Vec<String> = COLUMNS.iter().map(|c| c.to_string()).collect();
+            header.extend(Inputs::csv_header().split(';').map(String::from));
+            writer.write_record(&header).map_err(|e| write_error(&e))?;
+            Ok(DatasetWriter::Csv(writer))
+        } else {
+            let mut writer = BufWriter::new(file);
+            writer.write_all(MAGIC).map_err(|e| write_error(&e))?;
+            writer
+                .write_all(&VERSION.to_le_bytes())
+                .map_err(|e| write_error(&e))?;
+            Ok(DatasetWriter::Binary(writer))
+        }
+    }
+
+    /// Continues `path` after its last complete sample, or creates it.
+    /// A sample which was cut off by an interrupted write is dropped.
+    pub fn append(path: &Path) -> Result<Self, String> {
+        let error = |e: std::io::Error| format!("Failed to open {}: {}", path.display(), e);
+        let complete = match std::fs::read(path) {
+            Ok(bytes) if is_csv(path) => {
+                match bytes.iter().rposition(|&b| b == b'\n') {
+                    // Without a complete line there isn't even a complete header.
+                    Some(newline) => newline as u64 + 1,
+                    None => 0,
+                }
+            }
+            Ok(bytes) if bytes.len() >= 8 => {
+                if &bytes[..4] != MAGIC {
+                    return Err(format!("{} is no dataset", path.display()));
+                }
+                let samples = (bytes.len() as u64 - 8) / RECORD_LENGTH;
+                8 + samples * RECORD_LENGTH
+            }
+            Ok(_) => 0,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(error(e)),
+        };
+        if complete == 0 {
+            return Self::create(path);
+        }
+        let file = OpenOptions::new().write(true).open(path).map_err(error)?;
+        file.set_len(complete).map_err(error)?;
+        drop(file);
+        let file = OpenOptions::new().append(true).open(path).map_err(error)?;
+        Ok(if is_csv(path) {
+            DatasetWriter::Csv(
+                csv::WriterBuilder::new()
+                    .delimiter(b';')
+                    .has_headers(false)
+                    .from_writer(file),
+            )
+        } else {
+            DatasetWriter::Binary(BufWriter::new(file))
+        })
+    }
+
+    pub fn write(&mut self, sample: &Sample) -> Result<(), String> {
+        let id = sample.position.position_id();
+        let gnu = sample.probabilities.to_gnu();
+        match self {
+            DatasetWriter::Csv(writer) => {
+                let mut record = vec![id];
+                record.extend(gnu.iter().map(|p| p.to_string()));
+                let inputs = Inputs::from_position(&sample.position).to_string();
+                record.extend(inputs.split(';').map(String::from));
+                writer.write_record(&record).map_err(|e| write_error(&e))
+            }
+            DatasetWriter::Binary(writer) => {
+                writer
+                    .write_all(id.as_bytes())
+                    .map_err(|e| write_error(&e))?;
+                for p in gnu {
+                    writer
+                        .write_all(&p.to_le_bytes())
+                        .map_err(|e| write_error(&e))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        match self {
+            DatasetWriter::Csv(writer) => writer.flush(),
+            DatasetWriter::Binary(writer) => writer.flush(),
+        }
+        .map_err(|e| write_error(&e))
+    }
+}
+
+fn write_error(e: &dyn std::fmt::Display) -> String {
+    format!("Failed to write dataset: {}", e)
+}
+
+/// Streams the samples of a file, without reading all of it.
+pub enum DatasetReader {
+    Csv(csv::StringRecordsIntoIter<File>),
+    Binary(BufReader<File>),
+}
+
+impl DatasetReader {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        if is_csv(path) {
+            let mut reader = csv::ReaderBuilder::new().delimiter(b';').from_reader(file);
+            let header = reader.headers().map_err(|e| read_error(&e))?;
+            if header.iter().take(COLUMNS.len()).ne(COLUMNS) {
+                return Err(format!(
+                    "{} doesn't start with the columns {}",
+                    path.display(),
+                    COLUMNS.join(";")
+                ));
+            }
+            Ok(DatasetReader::Csv(reader.into_records()))
+        } else {
+            let mut reader = BufReader::new(file);
+            let mut header = [0; 8];
+            reader
+                .read_exact(&mut header)
+                .map_err(|_| format!("{} is no dataset", path.display()))?;
+            if &header[..4] != MAGIC {
+                return Err(format!("{} is no dataset", path.display()));
+            }
+            let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+            if version > VERSION {
+                return Err(format!(
+                    "{} has version {}, only {} is supported",
+                    path.display(),
+                    version,
+                    VERSION
+                ));
+            }
+            Ok(DatasetReader::Binary(reader))
+        }
+    }
+
+    fn next_csv(record: csv::Result<csv::StringRecord>) -> Result<Sample, String> {
+        let record = record.map_err(|e| read_error(&e))?;
+        let field = |i: usize| record.get(i).ok_or_else(|| "Missing column".to_string());
+        let position = parse_position_id(field(0)?)?;
+        let mut gnu = [0.0; 5];
+        for (i, p) in gnu.iter_mut().enumerate() {
+            *p = field(i + 1)?
+                .parse()
+                .map_err(|_| format!("Invalid probability '{}'", field(i + 1).unwrap()))?;
+        }
+        Ok(Sample {
+            position,
+            probabilities: Probabilities::from(&gnu),
+        })
+    }
+
+    /// `None` at the end of the file, an error if it ends within a sample.
+    fn next_binary(reader: &mut BufReader<File>) -> Option<Result<Sample, String>> {
+        let mut bytes = [0; RECORD_LENGTH as usize];
+        let mut read = 0;
+        while read < bytes.len() {
+            match reader.read(&mut bytes[read..]) {
+                Ok(0) if read == 0 => return None,
+                Ok(0) => return Some(Err("Dataset ends within a sample".to_string())),
+                Ok(n) => read += n,
+                Err(e) => return Some(Err(read_error(&e))),
+            }
+        }
+        let id = match std::str::from_utf8(&bytes[..ID_LENGTH]) {
+            Ok(id) => id,
+            Err(_) => return Some(Err("Invalid position ID".to_string())),
+        };
+        let position = match parse_position_id(id) {
+            Ok(position) => position,
+            Err(e) => return Some(Err(e)),
+        };
+        let mut gnu = [0.0; 5];
+        for (p, chunk) in gnu.iter_mut().zip(bytes[ID_LENGTH..].chunks_exact(4)) {
+            *p = f32::from_le_bytes(chunk.try_into().unwrap());
+        }
+        Some(Ok(Sample {
+            position,
+            probabilities: Probabilities::from(&gnu),
+        }))
+    }
+}
+
+impl Iterator for DatasetReader {
+    type Item = Result<Sample, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            DatasetReader::Csv(records) => records.next().map(Self::next_csv),
+            DatasetReader::Binary(reader) => Self::next_binary(reader),
+        }
+    }
+}
+
+fn read_error(e: &dyn std::fmt::Display) -> String {
+    format!("Failed to read dataset: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dataset::{DatasetReader, DatasetWriter, Sample};
+    use crate::probabilities::Probabilities;
+    use crate::test_dir::TestDir;
+    use bkgm::{pos, Position};
+
+    fn samples() -> Vec<Sample> {
+        vec![
+            Sample {
+                position: Position::from_id("4HPwATDgc/ABMA".to_string()),
+                probabilities: Probabilities::from(&[0.5, 0.125, 0.0, 0.125, 0.0]),
+            },
+            Sample {
+                position: pos!(x 1:1; o 24:1),
+                probabilities: Probabilities::from(&[1.0, 0.0, 0.0, 0.0, 0.0]),
+            },
+        ]
+    }
+
+    #[test]
+    fn csv_and_binary_round_trip() {
+        let dir = TestDir::new("dataset");
+        for name in ["samples.csv", "samples.bin"] {
+            let path = dir.join(name);
+            let mut writer = DatasetWriter::create(&path).unwrap();
+            for sample in samples() {
+                writer.write(&sample).unwrap();
+            }
+            writer.flush().unwrap();
+            drop(writer);
+
+            let read: Vec<Sample> = DatasetReader::open(&path)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(read, samples());
+        }
+        let csv = std::fs::read_to_string(dir.join("samples.csv")).unwrap();
+        assert!(csv.starts_with("position_id;win;win_g;win_b;lose_g;lose_b;x_off;o_off;"));
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let dir = TestDir::new("truncated");
+        let path = dir.join("samples.bin");
+        let mut writer = DatasetWriter::create(&path).unwrap();
+        writer.write(&samples()[0]).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+        let mut reader = DatasetReader::open(&path).unwrap();
+        assert!(reader.next().unwrap().is_err());
+
+        std::fs::write(&path, "nonsense").unwrap();
+        assert!(DatasetReader::open(&path).is_err());
+    }
+
+    #[test]
+    fn append_after_an_interrupted_write() {
+        let dir = TestDir::new("append");
+        for name in ["samples.csv", "samples.bin"] {
+            let path = dir.join(name);
+            let mut writer = DatasetWriter::append(&path).unwrap();
+            writer.write(&samples()[0]).unwrap();
+            writer.flush().unwrap();
+            drop(writer);
+            let bytes = std::fs::read(&path).unwrap();
+            let mut cut = bytes.clone();
+            cut.extend_from_slice(&bytes[bytes.len() - 10..bytes.len() - 1]);
+            std::fs::write(&path, cut).unwrap();
+
+            let mut writer = DatasetWriter::append(&path).unwrap();
+            writer.write(&samples()[1]).unwrap();
+            writer.flush().unwrap();
+            drop(writer);
+            let read: Vec<Sample> = DatasetReader::open(&path)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(read, samples());
+        }
+    }
+}
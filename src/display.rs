@@ -0,0 +1,74 @@
+use bkgm::Position;
+
+const ROWS: usize = 5;
+
+/// ASCII board from the perspective of x, who moves from 24 down to 1 and is shown as `X`.
+///
+/// Stacks higher than five checkers show their size in the last row.
+pub fn ascii_board(position: &Position) -> String {
+    let top: Vec<usize> = (13..=24).collect();
+    let bottom: Vec<usize> = (1..=12).rev().collect();
+
+    let mut lines = vec![points_line(&top)];
+    for row in 0..ROWS {
+        lines.push(checkers_line(position, &top, row));
+    }
+    lines.push(String::new());
+    for row in (0..ROWS).rev() {
+        lines.push(checkers_line(position, &bottom, row));
+    }
+    lines.push(points_line(&bottom));
+    lines.push(format!(
+        "Bar: X {} O {}   Off: X {} O {}",
+        position.x_bar(),
+        position.o_bar(),
+        position.x_off(),
+        position.o_off()
+    ));
+    lines.join("\n")
+}
+
+fn points_line(points: &[usize]) -> String {
+    let labels: Vec<String> = points.iter().map(|p| format!("{:>3}", p)).collect();
+    format!("{} |{}", labels[..6].concat(), labels[6..].concat())
+}
+
+fn checkers_line(position: &Position, points: &[usize], row: usize) -> String {
+    let cells: Vec<String> = points
+        .iter()
+        .map(|&point| {
+            let pip = position.pip(point);
+            let count = pip.unsigned_abs() as usize;
+            let checker = if pip > 0 { "X" } else { "O" };
+            let cell = if count > ROWS && row == ROWS - 1 {
+                count.to_string()
+            } else if count > row {
+                checker.to_string()
+            } else {
+                ".".to_string()
+            };
+            format!("{:>3}", cell)
+        })
+        .collect();
+    format!("{} |{}", cells[..6].concat(), cells[6..].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::display::ascii_board;
+    use bkgm::pos;
+
+    #[test]
+    fn stacks_and_bar() {
+        let board = ascii_board(&pos!(x 24:2, 6:7, 25:1; o 1:1, 19:3));
+        let lines: Vec<&str> = board.lines().collect();
+        assert_eq!(lines.len(), 14);
+        assert_eq!(lines[0], " 13 14 15 16 17 18 | 19 20 21 22 23 24");
+        assert_eq!(lines[1], "  .  .  .  .  .  . |  O  .  .  .  .  X");
+        assert_eq!(lines[3], "  .  .  .  .  .  . |  O  .  .  .  .  .");
+        assert_eq!(lines[7], "  .  .  .  .  .  . |  7  .  .  .  .  .");
+        assert_eq!(lines[11], "  .  .  .  .  .  . |  X  .  .  .  .  O");
+        assert_eq!(lines[12], " 12 11 10  9  8  7 |  6  5  4  3  2  1");
+        assert_eq!(lines[13], "Bar: X 1 O 0   Off: X 5 O 11");
+    }
+}
@@ -0,0 +1,236 @@
+//! Line based engine protocol, so that GUIs and scripts can use our evaluators over stdin/stdout.
+//!
+//! Every command is a single line, every response is one or more lines. Positions are given and
+//! returned as GNU Backgammon Position IDs, always from the perspective of the player on roll.
+//! Positions after a move are therefore from the perspective of the opponent.
+//!
+//! | Command                 | Response                                                        |
+//! |-------------------------|-----------------------------------------------------------------|
+//! | `new`                   | `ok` and sets the starting position, clears the dice             |
+//! | `position <id>`         | `ok`, sets the position by Position ID and clears the dice       |
+//! | `position <XGID>`       | `ok`, sets the position and, if the XGID contains a roll, dice   |
+//! | `dice <d1> <d2>`        | `ok`                                                            |
+//! | `eval`                  | `eval <win> <win_g> <win_b> <lose_g> <lose_b> <equity>`         |
+//! | `bestmove`              | `bestmove <id>`                                                 |
+//! | `moves [n]`             | `moves <k>` followed by `k` lines `<id> <equity>`, best first    |
+//! | `cube`                  | `cube <action> <no double> <double take> <double pass>`        |
+//! | `quit`                  | no response, the engine stops                                   |
+//!
+//! Probabilities use the GNU format, so `win_g` includes backgammons. Equities are cubeless and
+//! from the perspective of the player on roll; for `moves` that is the player making the move.
+//! `<action>` is one of `no-double`, `double-take`, `double-pass` and `too-good`.
+//! Invalid commands are answered with `error <message>`, the engine keeps running.
+
+use std::io::{self, BufRead, Write};
+
+use crate::cube::CubeDecision;
+use crate::evaluator::PositionEvaluator;
+use crate::notation::{parse_dice, parse_position};
+use bkgm::GameState::{GameOver, Ongoing};
+use bkgm::{Dice, State};
+
+pub struct Engine<G: State, E: PositionEvaluator<G>> {
+    evaluator: E,
+    position: G,
+    dice: Option<Dice>,
+}
+
+impl<G: State, E: PositionEvaluator<G>> Engine<G, E> {
+    pub fn new(evaluator: E) -> Self {
+        Self {
+            evaluator,
+            position: G::new(),
+            dice: None,
+        }
+    }
+
+    /// Reads commands until `quit` or the end of `input`.
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            match self.handle(&line) {
+                Some(response) => {
+                    for line in response {
+                        writeln!(output, "{}", line)?;
+                    }
+                    output.flush()?;
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the response lines for a single command, `None` if the engine should stop.
+    pub fn handle(&mut self, line: &str) -> Option<Vec<String>> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Some(vec![]),
+        };
+        let args: Vec<&str> = words.collect();
+        let response = match command {
+            "quit" => return None,
+            "new" => {
+                self.position = G::new();
+                self.dice = None;
+                Ok(vec!["ok".to_string()])
+            }
+            "position" => self.set_position(&args.join(" ")),
+            "dice" => self.set_dice(&args.join(" ")),
+            "eval" => self.eval(),
+            "bestmove" => self.best_move(),
+            "moves" => self.moves(args.first()),
+            "cube" => self.cube(),
+            _ => Err(format!("unknown command '{}'", command)),
+        };
+        Some(response.unwrap_or_else(|e| vec![format!("error {}", e)]))
+    }
+
+    fn set_position(&mut self, input: &str) -> Result<Vec<String>, String> {
+        let (position, dice) = parse_position(input)?;
+        self.position = G::from_position(position);
+        self.dice = dice;
+        Ok(vec!["ok".to_string()])
+    }
+
+    fn set_dice(&mut self, input: &str) -> Result<Vec<String>, String> {
+        self.dice = Some(parse_dice(input)?);
+        Ok(vec!["ok".to_string()])
+    }
+
+    fn ongoing(&self) -> Result<(), String> {
+        match self.position.game_state() {
+            Ongoing => Ok(()),
+            GameOver(_) => Err("game is over".to_string()),
+        }
+    }
+
+    fn rolled(&self) -> Result<Dice, String> {
+        self.ongoing()?;
+        self.dice.ok_or_else(|| "no dice set".to_string())
+    }
+
+    fn eval(&self) -> Result<Vec<String>, String> {
+        self.ongoing()?;
+        let probs = self.evaluator.eval(&self.position);
+        let gnu = probs.to_gnu();
+        Ok(vec![format!(
+            "eval {:.5} {:.5} {:.5} {:.5} {:.5} {:.5}",
+            gnu[0],
+            gnu[1],
+            gnu[2],
+            gnu[3],
+            gnu[4],
+            probs.equity()
+        )])
+    }
+
+    fn best_move(&self) -> Result<Vec<String>, String> {
+        let dice = self.rolled()?;
+        let best = self.evaluator.best_position(&self.position, &dice);
+        Ok(vec![format!("bestmove {}", best.position().position_id())])
+    }
+
+    fn moves(&self, limit: Option<&&str>) -> Result<Vec<String>, String> {
+        let dice = self.rolled()?;
+        let limit = match limit {
+            Some(limit) => limit
+                .parse::<usize>()
+                .map_err(|_| format!("invalid number of moves '{}'", limit))?,
+            None => usize::MAX,
+        };
+        let ranked = self.evaluator.ranked_positions(&self.position, &dice);
+        let mut response = Vec::with_capacity(ranked.len().min(limit) + 1);
+        response.push(format!("moves {}", ranked.len().min(limit)));
+        for (position, probs) in ranked.iter().take(limit) {
+            response.push(format!(
+                "{} {:.5}",
+                position.position().position_id(),
+                probs.equity()
+            ));
+        }
+        Ok(response)
+    }
+
+    fn cube(&self) -> Result<Vec<String>, String> {
+        self.ongoing()?;
+        let decision = CubeDecision::new(&self.evaluator.eval(&self.position));
+        Ok(vec![format!(
+            "cube {} {:.5} {:.5} {:.5}",
+            decision.action(),
+            decision.no_double,
+            decision.double_take,
+            decision.double_pass
+        )])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Engine;
+    use crate::evaluator::FakeEvaluator;
+    use bkgm::{Hypergammon, State};
+
+    fn script(commands: &str) -> Vec<String> {
+        let mut engine = Engine::<Hypergammon, _>::new(FakeEvaluator { win: 0.5 });
+        let mut output = Vec::new();
+        engine.run(commands.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn best_move_is_first_ranked_move() {
+        let start = Hypergammon::new().position().position_id();
+        let output = script(&format!("position {}\ndice 3 1\nbestmove\nmoves\n", start));
+        assert_eq!(output[0], "ok");
+        assert_eq!(output[1], "ok");
+        let best = output[2].strip_prefix("bestmove ").unwrap();
+        let count: usize = output[3].strip_prefix("moves ").unwrap().parse().unwrap();
+        assert_eq!(output.len(), 4 + count);
+        assert!(output[4].starts_with(best));
+
+        let equities: Vec<f32> = output[4..]
+            .iter()
+            .map(|line| line.split(' ').nth(1).unwrap().parse().unwrap())
+            .collect();
+        assert!(equities.windows(2).all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn moves_are_limited() {
+        let output = script("new\ndice 6 5\nmoves 2\n");
+        assert_eq!(output[2], "moves 2");
+        assert_eq!(output.len(), 5);
+    }
+
+    #[test]
+    fn errors_keep_engine_running() {
+        let output = script("bestmove\nfoo\ndice 7 1\nposition abc\neval\n");
+        assert_eq!(output.len(), 5);
+        assert_eq!(output[0], "error no dice set");
+        assert_eq!(output[1], "error unknown command 'foo'");
+        assert!(output[2].starts_with("error "));
+        assert!(output[3].starts_with("error "));
+        assert!(output[4].starts_with("eval "));
+        assert_eq!(output[4].split(' ').count(), 7);
+    }
+
+    #[test]
+    fn cube_response() {
+        let output = script("cube\n");
+        let words: Vec<&str> = output[0].split(' ').collect();
+        assert_eq!(words[0], "cube");
+        assert_eq!(words.len(), 5);
+    }
+
+    #[test]
+    fn quit_stops_reading() {
+        let output = script("new\nquit\nnew\n");
+        assert_eq!(output, vec!["ok"]);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::probabilities::Probabilities;
+use bkgm::GameState::{GameOver, Ongoing};
 use bkgm::{Dice, State};
 use fastrand;
 
@@ -5,6 +7,48 @@ pub trait Evaluator<G: State>: Sized {
     fn best_position(&self, pos: &G, dice: &Dice) -> G;
 }
 
+/// Lets a `Duel` borrow evaluators which are expensive to copy.
+impl<G: State, E: Evaluator<G>> Evaluator<G> for &E {
+    fn best_position(&self, pos: &G, dice: &Dice) -> G {
+        (*self).best_position(pos, dice)
+    }
+}
+
+/// Evaluators which can also judge a single position, not only pick a move.
+pub trait PositionEvaluator<G: State>: Evaluator<G> {
+    /// Cubeless probabilities from the perspective of the player on roll in `pos`.
+    fn eval(&self, pos: &G) -> Probabilities;
+
+    /// Same as `eval` for several positions, evaluators may do this in one go.
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        positions.iter().map(|pos| self.eval(pos)).collect()
+    }
+
+    /// All legal moves for `dice`, best first.
+    /// The probabilities are from the perspective of the player who made the move.
+    fn ranked_positions(&self, pos: &G, dice: &Dice) -> Vec<(G, Probabilities)> {
+        let positions = pos.possible_positions(dice);
+        let ongoing: Vec<G> = positions
+            .iter()
+            .filter(|p| p.game_state() == Ongoing)
+            .copied()
+            .collect();
+        let mut evaluations = self.eval_batch(&ongoing).into_iter();
+        let mut ranked: Vec<(G, Probabilities)> = positions
+            .into_iter()
+            .map(|p| {
+                let probs = match p.game_state() {
+                    GameOver(result) => Probabilities::from_result(&result),
+                    Ongoing => evaluations.next().unwrap(),
+                };
+                (p, probs.flip())
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.1.equity().partial_cmp(&a.1.equity()).unwrap());
+        ranked
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct RandomEvaluator;
 
@@ -0,0 +1,47 @@
+use crate::evaluator::{Evaluator, PositionEvaluator};
+use crate::probabilities::Probabilities;
+use bkgm::{Dice, State};
+
+/// Deterministic evaluator for the protocol tests.
+/// Moves the checkers furthest back as little as possible, claims a fixed winning chance.
+#[derive(Clone, Copy)]
+pub struct FakeEvaluator {
+    pub win: f32,
+}
+
+impl<G: State> Evaluator<G> for FakeEvaluator {
+    fn best_position(&self, pos: &G, dice: &Dice) -> G {
+        self.ranked_positions(pos, dice)[0].0
+    }
+}
+
+impl<G: State> PositionEvaluator<G> for FakeEvaluator {
+    fn eval(&self, _pos: &G) -> Probabilities {
+        Probabilities {
+            win_n: self.win,
+            win_g: 0.0,
+            win_b: 0.0,
+            lose_n: 1.0 - self.win,
+            lose_g: 0.0,
+            lose_b: 0.0,
+        }
+    }
+
+    fn ranked_positions(&self, pos: &G, dice: &Dice) -> Vec<(G, Probabilities)> {
+        // The moved checkers are negative in the positions after the move. Of equally good
+        // moves the last one generated is the best, reversing keeps that with a stable sort.
+        let mut positions = pos.possible_positions(dice);
+        positions.reverse();
+        positions.sort_by_key(|p| {
+            std::cmp::Reverse(
+                (1..=24)
+                    .map(|i| (25 - i).pow(2) * (-p.pip(i)).max(0) as usize)
+                    .sum::<usize>(),
+            )
+        });
+        positions
+            .into_iter()
+            .map(|p| (p, self.eval(&p).flip()))
+            .collect()
+    }
+}
@@ -1,4 +1,4 @@
-use crate::evaluator::Evaluator;
+use crate::evaluator::{Evaluator, PositionEvaluator};
 use crate::fstate::FState;
 use crate::probabilities::Probabilities;
 use bkgm::{utils::mcomb, Hypergammon, State};
@@ -35,6 +35,18 @@ impl Evaluator<FState<Hypergammon>> for HyperEvaluator {
     }
 }
 
+impl PositionEvaluator<Hypergammon> for HyperEvaluator {
+    fn eval(&self, pos: &Hypergammon) -> Probabilities {
+        self.probs[pos.dbhash()]
+    }
+}
+
+impl PositionEvaluator<FState<Hypergammon>> for HyperEvaluator {
+    fn eval(&self, pos: &FState<Hypergammon>) -> Probabilities {
+        self.probs[pos.dbhash()]
+    }
+}
+
 impl HyperEvaluator {
     pub fn new() -> Option<Self> {
         Self::from_file("data/hyper.db")
@@ -1,7 +1,11 @@
 mod evaluator;
+#[cfg(test)]
+mod fake;
 mod hyper;
 mod pubeval;
 
 pub use evaluator::*;
+#[cfg(test)]
+pub use fake::*;
 pub use hyper::*;
 pub use pubeval::*;
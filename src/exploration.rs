@@ -0,0 +1,255 @@
+//! Exploration policies for training games, see `TDConfig::exploration`.
+
+use crate::evaluator::PositionEvaluator;
+use bkgm::{Dice, State};
+use burn::config::Config;
+use std::str::FromStr;
+
+/// A value which changes linearly from `start` to `end` over the first `episodes` games.
+#[derive(Config, Debug, Copy, PartialEq)]
+pub struct Schedule {
+    pub start: f64,
+    pub end: f64,
+    #[config(default = 0)]
+    pub episodes: usize,
+}
+
+impl Schedule {
+    pub fn constant(value: f64) -> Self {
+        Self::new(value, value)
+    }
+
+    pub fn value(&self, episode: usize) -> f64 {
+        if episode >= self.episodes {
+            return self.end;
+        }
+        let progress = episode as f64 / self.episodes as f64;
+        self.start + (self.end - self.start) * progress
+    }
+}
+
+/// How the model picks its moves in training games.
+#[derive(Config, Debug, PartialEq)]
+pub enum Exploration {
+    /// Always the move with the best equity.
+    Greedy,
+    /// A uniformly random legal move with probability ε.
+    EpsilonGreedy { epsilon: Schedule },
+    /// Moves with probability proportional to `exp(equity / temperature)`.
+    Softmax { temperature: Schedule },
+    /// The best move after adding normal noise with standard deviation `sigma` to every equity.
+    Noise { sigma: Schedule },
+}
+
+impl Exploration {
+    /// The move from `pos` in game `episode`, and the greedy move if that one isn't it.
+    pub fn choose<G: State, E: PositionEvaluator<G>>(
+        &self,
+        model: &E,
+        pos: &G,
+        dice: &Dice,
+        episode: usize,
+        rng: &mut fastrand::Rng,
+    ) -> (G, Option<G>) {
+        if matches!(self, Exploration::Greedy) {
+            return (model.best_position(pos, dice), None);
+        }
+        let ranked = model.ranked_positions(pos, dice);
+        let equities: Vec<f64> = ranked
+            .iter()
+            .map(|(_, probs)| probs.equity() as f64)
+            .collect();
+        let index = match self {
+            Exploration::Greedy => 0,
+            Exploration::EpsilonGreedy { epsilon } => {
+                if rng.f64() < epsilon.value(episode) {
+                    rng.usize(..ranked.len())
+                } else {
+                    0
+                }
+            }
+            Exploration::Softmax { temperature } => {
+                softmax_sample(&equities, temperature.value(episode), rng)
+            }
+            Exploration::Noise { sigma } => {
+                let sigma = sigma.value(episode);
+                argmax(equities.iter().map(|equity| equity + sigma * normal(rng)))
+            }
+        };
+        let chosen = ranked[index].0;
+        let greedy = ranked[0].0;
+        (chosen, (chosen != greedy).then_some(greedy))
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    /// `value` or `start:end:episodes`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || {
+            format!(
+                "Invalid schedule '{}', expected value or start:end:episodes",
+                s
+            )
+        };
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            [value] => Ok(Schedule::constant(value.parse().map_err(|_| error())?)),
+            [start, end, episodes] => Ok(Schedule::new(
+                start.parse().map_err(|_| error())?,
+                end.parse().map_err(|_| error())?,
+            )
+            .with_episodes(episodes.parse().map_err(|_| error())?)),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl FromStr for Exploration {
+    type Err = String;
+
+    /// `greedy`, or `epsilon`, `softmax` or `noise` with a schedule, e.g. `softmax=1:0.1:100000`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, schedule) = s.split_once('=').unwrap_or((s, ""));
+        let schedule = || Schedule::from_str(schedule);
+        match name.to_lowercase().as_str() {
+            "greedy" => Ok(Exploration::Greedy),
+            "epsilon" => Ok(Exploration::EpsilonGreedy {
+                epsilon: schedule()?,
+            }),
+            "softmax" => Ok(Exploration::Softmax {
+                temperature: schedule()?,
+            }),
+            "noise" => Ok(Exploration::Noise { sigma: schedule()? }),
+            _ => Err(format!(
+                "Unknown exploration '{}', expected greedy, epsilon, softmax or noise",
+                name
+            )),
+        }
+    }
+}
+
+/// Index sampled with probability proportional to `exp(equity / temperature)`,
+/// the best one if the temperature isn't positive.
+fn softmax_sample(equities: &[f64], temperature: f64, rng: &mut fastrand::Rng) -> usize {
+    if temperature <= 0.0 {
+        return argmax(equities.iter().copied());
+    }
+    let max = equities.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let weights: Vec<f64> = equities
+        .iter()
+        .map(|equity| ((equity - max) / temperature).exp())
+        .collect();
+    let mut target = rng.f64() * weights.iter().sum::<f64>();
+    for (i, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return i;
+        }
+        target -= weight;
+    }
+    weights.len() - 1
+}
+
+fn argmax(values: impl Iterator<Item = f64>) -> usize {
+    values
+        .enumerate()
+        .fold((0, f64::NEG_INFINITY), |best, (i, value)| {
+            if value > best.1 {
+                (i, value)
+            } else {
+                best
+            }
+        })
+        .0
+}
+
+/// Standard normal sample, Box-Muller.
+fn normal(rng: &mut fastrand::Rng) -> f64 {
+    let u = 1.0 - rng.f64();
+    let v = rng.f64();
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{device, DefaultBackend};
+    use crate::evaluator::Evaluator;
+    use crate::exploration::{softmax_sample, Exploration, Schedule};
+    use crate::fstate::FState;
+    use crate::model::{ModelConfig, TDModel};
+    use bkgm::{pos, Backgammon, Dice, State};
+    use std::str::FromStr;
+
+    #[test]
+    fn schedule() {
+        let schedule = Schedule::new(1.0, 0.0).with_episodes(100);
+        assert_eq!(schedule.value(0), 1.0);
+        assert_eq!(schedule.value(25), 0.75);
+        assert_eq!(schedule.value(100), 0.0);
+        assert_eq!(schedule.value(1000), 0.0);
+        assert_eq!(Schedule::constant(0.5).value(0), 0.5);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Exploration::from_str("greedy"), Ok(Exploration::Greedy));
+        assert_eq!(
+            Exploration::from_str("epsilon=0.1"),
+            Ok(Exploration::EpsilonGreedy {
+                epsilon: Schedule::constant(0.1)
+            })
+        );
+        assert_eq!(
+            Exploration::from_str("softmax=1:0.1:1000"),
+            Ok(Exploration::Softmax {
+                temperature: Schedule::new(1.0, 0.1).with_episodes(1000)
+            })
+        );
+        assert!(Exploration::from_str("noise").is_err());
+        assert!(Exploration::from_str("boltzmann=1").is_err());
+    }
+
+    #[test]
+    fn no_exploration_plays_the_greedy_move() {
+        let model =
+            TDModel::<DefaultBackend>::new(ModelConfig::new().with_neurons(8), &device(true));
+        let epsilon = Exploration::EpsilonGreedy {
+            epsilon: Schedule::constant(0.0),
+        };
+        let mut rng = fastrand::Rng::with_seed(2);
+        // The second position has moves which end the game.
+        for state in [
+            Backgammon::new(),
+            Backgammon::from_position(pos!(x 1:1, 2:1; o 24:1)),
+        ] {
+            for turn in [true, false] {
+                let pos = FState { state, turn };
+                for dice in [Dice::new(2, 1), Dice::new(6, 4), Dice::new(3, 3)] {
+                    let greedy = model.best_position(&pos, &dice);
+                    assert_eq!(
+                        epsilon.choose(&model, &pos, &dice, 0, &mut rng),
+                        (greedy, None)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn softmax_temperatures() {
+        let mut rng = fastrand::Rng::with_seed(1);
+        let equities = [0.2, 0.5, -0.1];
+        assert_eq!(softmax_sample(&equities, 0.0, &mut rng), 1);
+        let counts = (0..3000).fold([0; 3], |mut counts, _| {
+            counts[softmax_sample(&equities, 0.01, &mut rng)] += 1;
+            counts
+        });
+        assert!(counts[1] > 2900);
+        let counts = (0..3000).fold([0; 3], |mut counts, _| {
+            counts[softmax_sample(&equities, 100.0, &mut rng)] += 1;
+            counts
+        });
+        assert!(counts.iter().all(|&count| count > 800));
+    }
+}
@@ -0,0 +1,154 @@
+//! GNU Backgammon "external player" interface.
+//!
+//! GNU Backgammon connects to a TCP socket (`external localhost:<port>` in gnubg) and sends one
+//! FIBS board per line. We answer every board on which we have to act with a single line:
+//!
+//! - Our turn with dice: the move in standard notation, e.g. `8/5 6/5`, or an empty line if no
+//!   move is possible.
+//! - Our turn without dice: `double` or `roll`.
+//! - We have been doubled: `take` or `drop`.
+//!
+//! Boards on which the opponent has to act are not answered. Cube decisions ignore the match
+//! score and are made as in a money game.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::evaluator::PositionEvaluator;
+use crate::fibs::{Action, FibsBoard};
+use crate::notation::format_moves;
+use bkgm::State;
+
+pub struct ExternalPlayer<G: State, E: PositionEvaluator<G>> {
+    evaluator: E,
+    phantom: std::marker::PhantomData<G>,
+}
+
+impl<G: State, E: PositionEvaluator<G>> ExternalPlayer<G, E> {
+    pub fn new(evaluator: E) -> Self {
+        Self {
+            evaluator,
+            phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Serves one GNU Backgammon connection after another.
+    pub fn listen(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            self.serve(stream?)?;
+        }
+        Ok(())
+    }
+
+    /// Answers boards until the connection is closed.
+    pub fn serve(&self, stream: TcpStream) -> io::Result<()> {
+        self.run(BufReader::new(stream.try_clone()?), stream)
+    }
+
+    pub fn run(&self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line == "exit" || line == "quit" {
+                break;
+            }
+            if !line.starts_with("board:") {
+                continue;
+            }
+            match line.parse::<FibsBoard>() {
+                Ok(board) => match self.reply(&board) {
+                    Ok(Some(reply)) => {
+                        writeln!(output, "{}", reply)?;
+                        output.flush()?;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        writeln!(output, "error {}", e)?;
+                        output.flush()?;
+                    }
+                },
+                Err(e) => {
+                    writeln!(output, "error {}", e)?;
+                    output.flush()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Our answer to `board`, `None` if we don't have to act.
+    pub fn reply(&self, board: &FibsBoard) -> Result<Option<String>, String> {
+        let action = match board.action(&self.evaluator)? {
+            Some(action) => action,
+            None => return Ok(None),
+        };
+        let reply = match action {
+            Action::Move(moves) => format_moves(&moves),
+            Action::Double => "double".to_string(),
+            Action::Roll => "roll".to_string(),
+            Action::Take => "take".to_string(),
+            Action::Drop => "drop".to_string(),
+        };
+        Ok(Some(reply))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use crate::evaluator::FakeEvaluator;
+    use crate::external::ExternalPlayer;
+    use bkgm::Backgammon;
+
+    /// Recorded boards, see `fibs::board` for the format.
+    const OUR_MOVE: &str = "board:You:gnubg:0:0:0:0:-2:0:0:0:0:5:0:3:0:0:0:-5:5:0:0:0:-3:0:-5:0:0:0:0:2:0:1:3:1:0:0:1:1:1:0:1:-1:0:25:0:0:0:0:2:0:0:0";
+    const OUR_CUBE: &str = "board:You:gnubg:0:0:0:0:-2:0:0:0:0:5:0:3:0:0:0:-5:5:0:0:0:-3:0:-5:0:0:0:0:2:0:1:0:0:0:0:1:1:1:0:1:-1:0:25:0:0:0:0:2:0:0:0";
+    const THEIR_MOVE: &str = "board:You:gnubg:0:0:0:0:-2:0:0:0:0:5:0:3:0:0:0:-5:5:0:0:0:-3:0:-5:0:0:0:0:2:0:-1:0:0:6:5:1:1:1:0:1:-1:0:25:0:0:0:0:2:0:0:0";
+    const DOUBLED: &str = "board:You:gnubg:0:0:0:0:-2:0:0:0:0:5:0:3:0:0:0:-5:5:0:0:0:-3:0:-5:0:0:0:0:2:0:-1:0:0:0:0:1:1:1:1:1:-1:0:25:0:0:0:0:2:0:0:0";
+
+    fn session(win: f32, boards: &[&str]) -> Vec<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            ExternalPlayer::<Backgammon, _>::new(FakeEvaluator { win })
+                .serve(stream)
+                .unwrap();
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        for board in boards {
+            writeln!(client, "{}", board).unwrap();
+        }
+        writeln!(client, "exit").unwrap();
+        let replies = BufReader::new(client)
+            .lines()
+            .map(|line| line.unwrap())
+            .collect();
+        server.join().unwrap();
+        replies
+    }
+
+    #[test]
+    fn replies_to_recorded_boards() {
+        let replies = session(0.5, &[OUR_CUBE, OUR_MOVE, THEIR_MOVE, DOUBLED]);
+        assert_eq!(replies, vec!["roll", "6/3 3/2", "take"]);
+    }
+
+    #[test]
+    fn cube_decisions_follow_evaluation() {
+        assert_eq!(session(0.72, &[OUR_CUBE]), vec!["double"]);
+        // When doubled, the evaluation is from the doubler's perspective.
+        assert_eq!(session(0.9, &[DOUBLED]), vec!["drop"]);
+    }
+
+    #[test]
+    fn invalid_board_is_reported() {
+        let replies = session(0.5, &["board:You:gnubg:0"]);
+        assert_eq!(replies.len(), 1);
+        assert!(replies[0].starts_with("error "));
+    }
+}
@@ -0,0 +1,271 @@
+use std::str::FromStr;
+
+use crate::cube::{CubeAction, CubeDecision};
+use crate::evaluator::PositionEvaluator;
+use crate::notation::moves_between;
+use bkgm::{Dice, Position, State};
+
+/// Board state in the FIBS format, as sent by FIBS servers and GNU Backgammon's external player.
+///
+/// See http://www.fibs.com/fibs_interface.html#board_state for the format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FibsBoard {
+    pub player: String,
+    pub opponent: String,
+    pub match_length: u32,
+    pub player_score: u32,
+    pub opponent_score: u32,
+    /// From the perspective of `player`, no matter whose turn it is.
+    pub position: Position,
+    /// Whether `player` has to act.
+    pub player_turn: bool,
+    /// Dice of the player on turn, `None` if they haven't rolled yet.
+    pub dice: Option<Dice>,
+    pub cube: u32,
+    pub player_may_double: bool,
+    pub opponent_may_double: bool,
+    pub was_doubled: bool,
+    /// Direction in which `player` moves on the FIBS board, needed to write FIBS move commands.
+    pub direction: i8,
+}
+
+/// What `player` should do on a board.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Checker moves `(from, to)` from the perspective of `player`, may be empty.
+    Move(Vec<(usize, usize)>),
+    Double,
+    Roll,
+    Take,
+    Drop,
+}
+
+const NUM_FIELDS: usize = 53;
+
+impl FromStr for FibsBoard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let fields: Vec<&str> = s.split(':').collect();
+        if fields.len() < NUM_FIELDS || fields[0] != "board" {
+            return Err(format!("Not a FIBS board: '{}'", s));
+        }
+        let int = |i: usize| -> Result<i32, String> {
+            fields[i]
+                .parse::<i32>()
+                .map_err(|_| format!("Invalid value '{}' in field {} of FIBS board", fields[i], i))
+        };
+
+        let color = int(41)?;
+        let direction = int(42)?;
+        if color.abs() != 1 || direction.abs() != 1 {
+            return Err("FIBS board has an invalid color or direction".to_string());
+        }
+
+        let mut pips = [0_i8; 26];
+        for (point, pip) in pips.iter_mut().enumerate().take(25).skip(1) {
+            // The player always moves from our point 24 to point 1.
+            let index = if direction == -1 { point } else { 25 - point };
+            let checkers = int(6 + index)?;
+            *pip = (checkers * color) as i8;
+        }
+        pips[25] = int(47)? as i8;
+        pips[0] = -int(48)? as i8;
+        let position = Position::try_from(pips).map_err(|e| e.to_string())?;
+
+        let player_turn = int(32)? == color;
+        let (die1, die2) = if player_turn {
+            (int(33)?, int(34)?)
+        } else {
+            (int(35)?, int(36)?)
+        };
+        let dice = match (die1, die2) {
+            (1..=6, 1..=6) => Some(Dice::new(die1 as usize, die2 as usize)),
+            _ => None,
+        };
+
+        Ok(FibsBoard {
+            player: fields[1].to_string(),
+            opponent: fields[2].to_string(),
+            match_length: int(3)? as u32,
+            player_score: int(4)? as u32,
+            opponent_score: int(5)? as u32,
+            position,
+            player_turn,
+            dice,
+            cube: int(37)? as u32,
+            player_may_double: int(38)? == 1,
+            opponent_may_double: int(39)? == 1,
+            was_doubled: int(40)? != 0,
+            direction: direction as i8,
+        })
+    }
+}
+
+impl FibsBoard {
+    /// Position from the perspective of the player who has to act.
+    pub fn position_on_turn(&self) -> Position {
+        if self.player_turn {
+            self.position
+        } else {
+            self.position.flip()
+        }
+    }
+
+    /// What `player` should do according to `evaluator`, `None` if it's the opponent's turn.
+    /// Fails if the evaluator picks a position which no legal move reaches.
+    ///
+    /// Cube decisions ignore the match score and are made as in a money game.
+    pub fn action<G: State, E: PositionEvaluator<G>>(
+        &self,
+        evaluator: &E,
+    ) -> Result<Option<Action>, String> {
+        if self.was_doubled {
+            // The doubler is on turn, so this is their decision.
+            if self.player_turn {
+                return Ok(None);
+            }
+            let doubler = G::from_position(self.position.flip());
+            let decision = CubeDecision::new(&evaluator.eval(&doubler));
+            return Ok(Some(if decision.should_take() {
+                Action::Take
+            } else {
+                Action::Drop
+            }));
+        }
+        if !self.player_turn {
+            return Ok(None);
+        }
+
+        let position = G::from_position(self.position);
+        match self.dice {
+            None => {
+                let decision = CubeDecision::new(&evaluator.eval(&position));
+                let double = self.player_may_double
+                    && matches!(
+                        decision.action(),
+                        CubeAction::DoubleTake | CubeAction::DoublePass
+                    );
+                Ok(Some(if double { Action::Double } else { Action::Roll }))
+            }
+            Some(dice) => {
+                let best = evaluator.best_position(&position, &dice);
+                let moves =
+                    moves_between(&self.position, &best.position(), &dice).ok_or_else(|| {
+                        format!(
+                            "The evaluator returned an illegal move to {}",
+                            best.position().position_id()
+                        )
+                    })?;
+                Ok(Some(Action::Move(moves)))
+            }
+        }
+    }
+
+    /// Converts a point from the perspective of `player` to the numbering on the FIBS board.
+    pub fn fibs_point(&self, point: usize) -> usize {
+        if self.direction == -1 {
+            point
+        } else {
+            25 - point
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::{Evaluator, FakeEvaluator, PositionEvaluator};
+    use crate::fibs::{Action, FibsBoard};
+    use crate::probabilities::Probabilities;
+    use bkgm::{pos, Backgammon, Dice, Position, State};
+
+    /// Example from the FIBS documentation, with dice added.
+    const BOARD: &str = "board:You:someplayer:3:0:0:0:-2:0:0:0:0:5:0:3:0:0:0:-5:5:0:0:0:-3:0:-5:0:0:0:0:2:0:1:6:2:0:0:1:1:1:0:1:-1:0:25:0:0:0:0:2:0:0:0";
+
+    #[test]
+    fn starting_position() {
+        let board: FibsBoard = BOARD.parse().unwrap();
+        assert_eq!(board.player, "You");
+        assert_eq!(board.opponent, "someplayer");
+        assert_eq!(board.match_length, 3);
+        assert_eq!(
+            board.position,
+            Position::from_id("4HPwATDgc/ABMA".to_string())
+        );
+        assert!(board.player_turn);
+        assert_eq!(board.dice, Some(Dice::new(6, 2)));
+        assert_eq!(board.cube, 1);
+        assert!(!board.was_doubled);
+        assert_eq!(board.fibs_point(24), 24);
+    }
+
+    #[test]
+    fn opposite_direction_and_bar() {
+        // Same position with colors and directions swapped, player has a checker on the bar.
+        let board = "board:You:someplayer:3:0:0:0:-1:0:0:0:0:5:0:3:0:0:0:-5:5:0:0:0:-3:0:-5:0:0:0:0:2:0:1:0:0:4:1:1:1:1:0:-1:1:25:0:0:0:1:0:0:0:0:0";
+        let board: FibsBoard = board.parse().unwrap();
+        assert_eq!(
+            board.position,
+            pos!(x 25:1, 24:1, 13:5, 8:3, 6:5; o 19:5, 17:3, 12:5, 1:2)
+        );
+        assert!(!board.player_turn);
+        assert_eq!(board.dice, Some(Dice::new(4, 1)));
+        assert_eq!(board.fibs_point(24), 1);
+    }
+
+    #[test]
+    fn invalid_boards() {
+        assert!("board:You:someplayer:3".parse::<FibsBoard>().is_err());
+        assert!(BOARD
+            .replace("board:", "bored:")
+            .parse::<FibsBoard>()
+            .is_err());
+    }
+
+    /// Answers every roll with a position which no move reaches.
+    struct IllegalEvaluator;
+
+    impl Evaluator<Backgammon> for IllegalEvaluator {
+        fn best_position(&self, _pos: &Backgammon, _dice: &Dice) -> Backgammon {
+            Backgammon::from_position(pos!(x 1:15; o 24:15))
+        }
+    }
+
+    impl PositionEvaluator<Backgammon> for IllegalEvaluator {
+        fn eval(&self, pos: &Backgammon) -> Probabilities {
+            FakeEvaluator { win: 0.5 }.eval(pos)
+        }
+    }
+
+    #[test]
+    fn illegal_move_is_an_error() {
+        let board: FibsBoard = BOARD.parse().unwrap();
+        assert!(board.action(&IllegalEvaluator).is_err());
+        assert!(board
+            .action::<Backgammon, _>(&FakeEvaluator { win: 0.5 })
+            .is_ok());
+    }
+
+    /// `BOARD` with some fields replaced.
+    fn with_fields(changes: &[(usize, &str)]) -> FibsBoard {
+        let mut fields: Vec<&str> = BOARD.split(':').collect();
+        for (i, value) in changes {
+            fields[*i] = value;
+        }
+        fields.join(":").parse().unwrap()
+    }
+
+    #[test]
+    fn only_the_doubled_player_answers_a_double() {
+        let evaluator = FakeEvaluator { win: 0.5 };
+        // After our double we are still on turn, the opponent has to decide.
+        let we_doubled = with_fields(&[(32, "1"), (33, "0"), (34, "0"), (40, "1")]);
+        assert_eq!(we_doubled.action::<Backgammon, _>(&evaluator), Ok(None));
+        let they_doubled = with_fields(&[(32, "-1"), (33, "0"), (34, "0"), (40, "1")]);
+        assert_eq!(
+            they_doubled.action::<Backgammon, _>(&evaluator),
+            Ok(Some(Action::Take))
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::net::{TcpStream, ToSocketAddrs};
+
+use crate::evaluator::PositionEvaluator;
+use crate::fibs::{Action, ClipMessage, FibsBoard};
+use bkgm::State;
+
+/// Version of the CLIP protocol we speak.
+const CLIP_VERSION: u32 = 1008;
+const CLIENT_NAME: &str = "td-gammon";
+
+#[derive(Debug, Clone)]
+pub struct FibsConfig {
+    pub user: String,
+    pub password: String,
+    /// Join every match we are invited to.
+    pub accept_invitations: bool,
+}
+
+/// Bot which plays on a FIBS compatible server, choosing moves and cube actions with `E`.
+pub struct FibsClient<G: State, E: PositionEvaluator<G>> {
+    evaluator: E,
+    config: FibsConfig,
+    /// Last board we acted on, FIBS repeats boards.
+    last_board: Option<FibsBoard>,
+    phantom: PhantomData<G>,
+}
+
+impl<G: State, E: PositionEvaluator<G>> FibsClient<G, E> {
+    pub fn new(evaluator: E, config: FibsConfig) -> Self {
+        Self {
+            evaluator,
+            config,
+            last_board: None,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn connect(&mut self, address: impl ToSocketAddrs) -> io::Result<()> {
+        let stream = TcpStream::connect(address)?;
+        self.run(BufReader::new(stream.try_clone()?), stream)
+    }
+
+    /// Logs in and plays until the server closes the connection.
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        // FIBS prompts with "login: " without a newline, but accepts the login right away.
+        let login = format!(
+            "login {} {} {} {}",
+            CLIENT_NAME, CLIP_VERSION, self.config.user, self.config.password
+        );
+        Self::send(&mut output, &login)?;
+
+        for line in input.lines() {
+            let message = ClipMessage::parse(&line?);
+            for command in self.handle(message)? {
+                Self::send(&mut output, &command)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn send(output: &mut impl Write, command: &str) -> io::Result<()> {
+        write!(output, "{}\r\n", command)?;
+        output.flush()
+    }
+
+    /// Commands to send in response to `message`.
+    pub fn handle(&mut self, message: ClipMessage) -> io::Result<Vec<String>> {
+        let commands = match message {
+            ClipMessage::LoginFailed => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "FIBS login failed",
+                ))
+            }
+            ClipMessage::Welcome { .. } => {
+                vec!["set boardstyle 3".to_string(), "toggle ready".to_string()]
+            }
+            ClipMessage::Invitation { name } if self.config.accept_invitations => {
+                vec![format!("join {}", name)]
+            }
+            ClipMessage::YourTurn | ClipMessage::Doubled { .. } => vec!["board".to_string()],
+            ClipMessage::Board(board) => self
+                .play(*board)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                .into_iter()
+                .collect(),
+            _ => vec![],
+        };
+        Ok(commands)
+    }
+
+    fn play(&mut self, board: FibsBoard) -> Result<Option<String>, String> {
+        if self.last_board.as_ref() == Some(&board) {
+            return Ok(None);
+        }
+        let action = match board.action(&self.evaluator)? {
+            Some(action) => action,
+            None => return Ok(None),
+        };
+        let command = match action {
+            Action::Move(moves) if moves.is_empty() => return Ok(None),
+            Action::Move(moves) => {
+                let moves: Vec<String> = moves
+                    .iter()
+                    .map(|(from, to)| {
+                        format!(
+                            "{}-{}",
+                            Self::point(&board, *from),
+                            Self::point(&board, *to)
+                        )
+                    })
+                    .collect();
+                format!("move {}", moves.join(" "))
+            }
+            Action::Double => "double".to_string(),
+            Action::Roll => "roll".to_string(),
+            Action::Take => "accept".to_string(),
+            Action::Drop => "reject".to_string(),
+        };
+        self.last_board = Some(board);
+        Ok(Some(command))
+    }
+
+    fn point(board: &FibsBoard, point: usize) -> String {
+        match point {
+            25 => "bar".to_string(),
+            0 => "off".to_string(),
+            point => board.fibs_point(point).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use crate::evaluator::FakeEvaluator;
+    use crate::fibs::{FibsClient, FibsConfig};
+    use bkgm::Backgammon;
+
+    enum Step {
+        /// Server sends a line
+        Send(&'static str),
+        /// Server waits for a command of the client
+        Receive,
+    }
+    use Step::{Receive, Send};
+
+    /// Replays `session` as a FIBS server and returns all commands the client sent.
+    fn mock_server(win: f32, session: Vec<Step>) -> Vec<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let config = FibsConfig {
+                user: "tdbot".to_string(),
+                password: "secret".to_string(),
+                accept_invitations: true,
+            };
+            FibsClient::<Backgammon, _>::new(FakeEvaluator { win }, config)
+                .connect(address)
+                .unwrap();
+        });
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut received = Vec::new();
+        for step in session {
+            match step {
+                Send(line) => write!(stream, "{}\r\n", line).unwrap(),
+                Receive => {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    received.push(line.trim_end().to_string());
+                }
+            }
+        }
+        drop(reader);
+        drop(stream);
+        client.join().unwrap();
+        received
+    }
+
+    /// Recorded boards, the bot plays with direction 1 on the FIBS board.
+    const BOT_TO_ROLL: &str = "board:tdbot:someplayer:1:0:0:0:-2:0:0:0:0:5:0:3:0:0:0:-5:5:0:0:0:-3:0:-5:0:0:0:0:2:0:-1:0:0:0:0:1:1:1:0:-1:1:25:0:0:0:0:0:2:0:0:0";
+    const BOT_TO_MOVE: &str = "board:tdbot:someplayer:1:0:0:0:-2:0:0:0:0:5:0:3:0:0:0:-5:5:0:0:0:-3:0:-5:0:0:0:0:2:0:-1:3:1:0:0:1:1:1:0:-1:1:25:0:0:0:0:0:2:0:0:0";
+    const BOT_DOUBLED: &str = "board:tdbot:someplayer:1:0:0:0:-2:0:0:0:0:5:0:3:0:0:0:-5:5:0:0:0:-3:0:-5:0:0:0:0:2:0:1:0:0:0:0:1:1:1:1:-1:1:25:0:0:0:0:0:2:0:0:0";
+
+    #[test]
+    fn login_and_play_a_turn() {
+        let received = mock_server(
+            0.5,
+            vec![
+                Receive,
+                Send("1 tdbot 1041253132 localhost"),
+                Receive,
+                Receive,
+                Send("someplayer wants to play a 1 point match with you."),
+                Receive,
+                Send("It's your turn. Please roll or double"),
+                Receive,
+                Send(BOT_TO_ROLL),
+                Receive,
+                Send("You roll 3 and 1."),
+                Send(BOT_TO_MOVE),
+                // Repeated boards are ignored, so the next command answers the double.
+                Send(BOT_TO_MOVE),
+                Receive,
+                Send("someplayer doubles. Type 'accept' or 'reject'."),
+                Receive,
+                Send(BOT_DOUBLED),
+                Receive,
+            ],
+        );
+        assert_eq!(
+            received,
+            vec![
+                "login td-gammon 1008 tdbot secret",
+                "set boardstyle 3",
+                "toggle ready",
+                "join someplayer",
+                "board",
+                "roll",
+                "move 19-22 22-23",
+                "board",
+                "accept",
+            ]
+        );
+    }
+
+    #[test]
+    fn cube_actions_follow_evaluation() {
+        let received = mock_server(0.72, vec![Receive, Send(BOT_TO_ROLL), Receive]);
+        assert_eq!(received[1], "double");
+        let received = mock_server(0.9, vec![Receive, Send(BOT_DOUBLED), Receive]);
+        assert_eq!(received[1], "reject");
+    }
+}
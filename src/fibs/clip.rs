@@ -0,0 +1,165 @@
+use crate::fibs::FibsBoard;
+
+/// Messages sent by a FIBS server to a client which logged in with the CLIP protocol.
+///
+/// See http://www.fibs.com/fibs_interface.html#clip for the numbered messages.
+/// Game related messages are plain text on FIBS, only the ones a bot needs are parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipMessage {
+    Welcome {
+        name: String,
+    },
+    OwnInfo,
+    MotdStart,
+    MotdEnd,
+    WhoInfo {
+        name: String,
+    },
+    WhoEnd,
+    Login {
+        name: String,
+    },
+    Logout {
+        name: String,
+    },
+    Says {
+        name: String,
+        message: String,
+    },
+    /// Clip messages 9 to 19, apart from `Says`
+    Chat {
+        code: u8,
+        text: String,
+    },
+    Board(Box<FibsBoard>),
+    Invitation {
+        name: String,
+    },
+    YourTurn,
+    Doubled {
+        name: String,
+    },
+    LoginFailed,
+    Text(String),
+}
+
+impl ClipMessage {
+    pub fn parse(line: &str) -> Self {
+        let line = line.trim();
+        if line.starts_with("board:") {
+            return match line.parse::<FibsBoard>() {
+                Ok(board) => ClipMessage::Board(Box::new(board)),
+                Err(_) => ClipMessage::Text(line.to_string()),
+            };
+        }
+
+        let (first, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let name = || rest.split(' ').next().unwrap_or("").to_string();
+        match first.parse::<u8>() {
+            Ok(1) => ClipMessage::Welcome { name: name() },
+            Ok(2) => ClipMessage::OwnInfo,
+            Ok(3) => ClipMessage::MotdStart,
+            Ok(4) => ClipMessage::MotdEnd,
+            Ok(5) => ClipMessage::WhoInfo { name: name() },
+            Ok(6) => ClipMessage::WhoEnd,
+            Ok(7) => ClipMessage::Login { name: name() },
+            Ok(8) => ClipMessage::Logout { name: name() },
+            Ok(12) => {
+                let (name, message) = rest.split_once(' ').unwrap_or((rest, ""));
+                ClipMessage::Says {
+                    name: name.to_string(),
+                    message: message.to_string(),
+                }
+            }
+            Ok(code) if (9..=19).contains(&code) => ClipMessage::Chat {
+                code,
+                text: rest.to_string(),
+            },
+            _ => Self::parse_text(line),
+        }
+    }
+
+    fn parse_text(line: &str) -> Self {
+        let first_word = || line.split(' ').next().unwrap_or("").to_string();
+        if line.contains("wants to play a") || line.contains("wants to resume a saved match") {
+            ClipMessage::Invitation { name: first_word() }
+        } else if line.starts_with("It's your turn") || line.starts_with("Please move") {
+            ClipMessage::YourTurn
+        } else if line.contains("doubles. Type 'accept' or 'reject'") {
+            ClipMessage::Doubled { name: first_word() }
+        } else if line.starts_with("Login incorrect") {
+            ClipMessage::LoginFailed
+        } else {
+            ClipMessage::Text(line.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fibs::ClipMessage;
+
+    #[test]
+    fn numbered_messages() {
+        assert_eq!(
+            ClipMessage::parse("1 tdbot 1041253132 192.168.1.308\r\n"),
+            ClipMessage::Welcome {
+                name: "tdbot".to_string()
+            }
+        );
+        assert_eq!(
+            ClipMessage::parse("7 someplayer someplayer logs in."),
+            ClipMessage::Login {
+                name: "someplayer".to_string()
+            }
+        );
+        assert_eq!(
+            ClipMessage::parse("12 someplayer Hello there"),
+            ClipMessage::Says {
+                name: "someplayer".to_string(),
+                message: "Hello there".to_string()
+            }
+        );
+        assert_eq!(
+            ClipMessage::parse("13 someplayer Hi all"),
+            ClipMessage::Chat {
+                code: 13,
+                text: "someplayer Hi all".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn game_messages() {
+        assert_eq!(
+            ClipMessage::parse("someplayer wants to play a 5 point match with you."),
+            ClipMessage::Invitation {
+                name: "someplayer".to_string()
+            }
+        );
+        assert_eq!(
+            ClipMessage::parse("someplayer doubles. Type 'accept' or 'reject'."),
+            ClipMessage::Doubled {
+                name: "someplayer".to_string()
+            }
+        );
+        assert_eq!(
+            ClipMessage::parse("It's your turn to move."),
+            ClipMessage::YourTurn
+        );
+        assert_eq!(
+            ClipMessage::parse("You roll 3 and 1."),
+            ClipMessage::Text("You roll 3 and 1.".to_string())
+        );
+    }
+
+    #[test]
+    fn boards() {
+        let board = "board:You:someplayer:3:0:0:0:-2:0:0:0:0:5:0:3:0:0:0:-5:5:0:0:0:-3:0:-5:0:0:0:0:2:0:1:6:2:0:0:1:1:1:0:1:-1:0:25:0:0:0:0:2:0:0:0";
+        assert!(matches!(ClipMessage::parse(board), ClipMessage::Board(_)));
+        assert!(matches!(
+            ClipMessage::parse("board:broken"),
+            ClipMessage::Text(_)
+        ));
+    }
+}
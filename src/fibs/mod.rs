@@ -0,0 +1,7 @@
+mod board;
+mod client;
+mod clip;
+
+pub use board::*;
+pub use client::*;
+pub use clip::*;
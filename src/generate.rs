@@ -0,0 +1,238 @@
+//! Labelled datasets for `supervised`: positions collected from games, deduplicated,
+//! then labelled by rollouts or by an evaluator such as `HyperEvaluator`.
+
+use crate::dataset::{DatasetReader, DatasetWriter, Sample};
+use crate::dicegen::{DiceGen, FastrandDice};
+use crate::evaluator::{Evaluator, PositionEvaluator};
+use crate::probabilities::Probabilities;
+use crate::rollout::rollout;
+use bkgm::{GameState::Ongoing, State};
+use burn::config::Config;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::path::Path;
+
+#[derive(Config)]
+pub struct GenerateConfig {
+    /// Games the positions are collected from.
+    #[config(default = 1000)]
+    pub games: usize,
+    /// Threads labelling positions.
+    #[config(default = 4)]
+    pub workers: usize,
+    /// Positions labelled before they are written, an interruption loses at most these.
+    #[config(default = 256)]
+    pub chunk: usize,
+    /// Seeds the dice of the games and the rollouts, so that a run can be resumed.
+    #[config(default = 0)]
+    pub seed: u64,
+}
+
+/// Labels positions for a dataset.
+pub trait Labeller<G: State>: Sync {
+    /// Cubeless probabilities from the perspective of the player on roll in `pos`.
+    /// `seed` is the same whenever the same position of the same run is labelled.
+    fn label(&self, pos: &G, seed: u64) -> Probabilities;
+}
+
+/// Rollouts with `evaluator` playing both sides.
+pub struct RolloutLabeller<E> {
+    evaluator: E,
+    games: usize,
+}
+
+impl<E> RolloutLabeller<E> {
+    pub fn new(evaluator: E, games: usize) -> Self {
+        Self { evaluator, games }
+    }
+}
+
+impl<G: State, E: Evaluator<G> + Sync> Labeller<G> for RolloutLabeller<E> {
+    fn label(&self, pos: &G, seed: u64) -> Probabilities {
+        rollout(
+            &self.evaluator,
+            pos,
+            self.games,
+            &mut FastrandDice::with_seed(seed),
+        )
+    }
+}
+
+/// Labels from an evaluator which judges positions itself, such as `HyperEvaluator`.
+pub struct EvaluatorLabeller<E>(pub E);
+
+impl<G: State, E: PositionEvaluator<G> + Sync> Labeller<G> for EvaluatorLabeller<E> {
+    fn label(&self, pos: &G, _seed: u64) -> Probabilities {
+        self.0.eval(pos)
+    }
+}
+
+fn position_id<G: State>(pos: &G) -> String {
+    pos.position().position_id()
+}
+
+/// Every position with a move to make in `config.games` games between `first` and `second`,
+/// without duplicates, in the order they were reached. The sides alternate between games.
+/// `first` and `second` may be the same evaluator for self-play.
+pub fn collect_positions<G, A, B>(first: &A, second: &B, config: &GenerateConfig) -> Vec<G>
+where
+    G: State,
+    A: Evaluator<G>,
+    B: Evaluator<G>,
+{
+    let mut seen = HashSet::new();
+    let mut positions = Vec::new();
+    for game in 0..config.games {
+        let seed = config.seed ^ (game as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let mut dicegen = FastrandDice::with_seed(seed);
+        let mut dice = dicegen.first_roll();
+        let mut state = G::new();
+        let mut first_to_move = game % 2 == 0;
+        while state.game_state() == Ongoing {
+            if seen.insert(position_id(&state)) {
+                positions.push(state);
+            }
+            state = if first_to_move {
+                first.best_position(&state, &dice)
+            } else {
+                second.best_position(&state, &dice)
+            };
+            dice = dicegen.roll();
+            first_to_move = !first_to_move;
+        }
+    }
+    positions
+}
+
+/// Labels `positions` with `workers` threads and appends them to the dataset at `path`.
+/// Positions which are already in it are skipped, so an interrupted run continues where it
+/// stopped. Returns the number of positions which were added.
+/// `progress` gets the positions labelled so far and those to label, before the first chunk
+/// and after every chunk.
+pub fn generate<G, L>(
+    config: &GenerateConfig,
+    labeller: &L,
+    positions: &[G],
+    path: &Path,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<usize, String>
+where
+    G: State + Sync,
+    L: Labeller<G>,
+{
+    let mut writer = DatasetWriter::append(path)?;
+    let mut done = HashSet::new();
+    for sample in DatasetReader::open(path)? {
+        done.insert(sample?.position.position_id());
+    }
+    let todo: Vec<(usize, &G)> = positions
+        .iter()
+        .enumerate()
+        .filter(|(_, pos)| !done.contains(&position_id(*pos)))
+        .collect();
+    progress(0, todo.len());
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.workers)
+        .build()
+        .map_err(|e| format!("Failed to start workers: {}", e))?;
+    let mut added = 0;
+    for chunk in todo.chunks(config.chunk.max(1)) {
+        let samples: Vec<Sample> = pool.install(|| {
+            chunk
+                .par_iter()
+                .map(|(index, pos)| {
+                    let seed = config.seed ^ (*index as u64).wrapping_mul(0x94d0_49bb_1331_11eb);
+                    Sample {
+                        position: pos.position(),
+                        probabilities: labeller.label(pos, seed),
+                    }
+                })
+                .collect()
+        });
+        for sample in &samples {
+            writer.write(sample)?;
+        }
+        writer.flush()?;
+        added += samples.len();
+        progress(added, todo.len());
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dataset::{DatasetReader, Sample};
+    use crate::evaluator::PubEval;
+    use crate::fstate::FState;
+    use crate::generate::{collect_positions, generate, GenerateConfig, Labeller};
+    use crate::probabilities::Probabilities;
+    use crate::test_dir::TestDir;
+    use bkgm::{Hypergammon, State};
+    use std::collections::HashSet;
+
+    /// The share of checkers borne off as the win probability, cheap and deterministic.
+    struct Checkers;
+
+    impl<G: State> Labeller<G> for Checkers {
+        fn label(&self, pos: &G, _seed: u64) -> Probabilities {
+            let win = pos.x_off() as f32 / G::NUM_CHECKERS as f32;
+            Probabilities::from(&[win, 0.0, 0.0, 0.0, 0.0])
+        }
+    }
+
+    fn read(path: &std::path::Path) -> Vec<Sample> {
+        DatasetReader::open(path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn positions_are_unique() {
+        let pubeval = PubEval::<FState<Hypergammon>>::new();
+        let config = GenerateConfig::new().with_games(5).with_seed(1);
+        let positions: Vec<FState<Hypergammon>> = collect_positions(&pubeval, &pubeval, &config);
+        let ids: HashSet<String> = positions
+            .iter()
+            .map(|pos| pos.position().position_id())
+            .collect();
+        assert_eq!(ids.len(), positions.len());
+        assert_eq!(positions, collect_positions(&pubeval, &pubeval, &config));
+    }
+
+    #[test]
+    fn resumes_after_an_interruption() {
+        let dir = TestDir::new("generate");
+        let pubeval = PubEval::<FState<Hypergammon>>::new();
+        let config = GenerateConfig::new()
+            .with_games(3)
+            .with_workers(2)
+            .with_chunk(4)
+            .with_seed(2);
+        let positions: Vec<FState<Hypergammon>> = collect_positions(&pubeval, &pubeval, &config);
+
+        let complete = dir.join("complete.bin");
+        let mut calls = Vec::new();
+        let progress = |added, todo| calls.push((added, todo));
+        assert_eq!(
+            generate(&config, &Checkers, &positions, &complete, progress).unwrap(),
+            positions.len()
+        );
+        let chunks = (positions.len() + 3) / 4;
+        assert_eq!(calls.len(), chunks + 1);
+        assert_eq!(calls[0], (0, positions.len()));
+        assert_eq!(calls[chunks], (positions.len(), positions.len()));
+
+        let resumed = dir.join("resumed.bin");
+        generate(&config, &Checkers, &positions[..5], &resumed, |_, _| {}).unwrap();
+        let mut bytes = std::fs::read(&resumed).unwrap();
+        bytes.truncate(bytes.len() - 3);
+        std::fs::write(&resumed, bytes).unwrap();
+        assert_eq!(
+            generate(&config, &Checkers, &positions, &resumed, |_, _| {}).unwrap(),
+            positions.len() - 4
+        );
+        assert_eq!(read(&resumed), read(&complete));
+    }
+}
@@ -1,5 +1,7 @@
 use bkgm::Position;
+use burn::config::Config;
 use std::fmt;
+use std::str::FromStr;
 
 pub(crate) const NUM_INPUTS: usize = 202;
 
@@ -87,6 +89,272 @@ impl Inputs {
     }
 }
 
+/// Turns a position into the inputs of a network, from the perspective of x who is on roll.
+pub trait Encoder {
+    /// Stored with checkpoints, so that a model is always fed the inputs it was trained with.
+    fn name(&self) -> &'static str;
+
+    fn num_inputs(&self) -> usize;
+
+    /// Writes the inputs of `pos` into `inputs`, which has a length of `num_inputs`.
+    /// Used to encode a batch of positions into one buffer without any allocations.
+    fn encode_into(&self, pos: &Position, inputs: &mut [f32]);
+
+    fn encode(&self, pos: &Position) -> Vec<f32> {
+        let mut inputs = vec![0.0; self.num_inputs()];
+        self.encode_into(pos, &mut inputs);
+        inputs
+    }
+}
+
+/// The available encoders, as a value for `ModelConfig`.
+#[derive(Config, Debug, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// `Inputs`, 202 values.
+    Standard,
+    /// Tesauro's original 198 values.
+    Tesauro,
+    /// `Standard` followed by hand-crafted features, 210 values.
+    Extended,
+}
+
+burn::constant!(Encoding);
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::Standard, Encoding::Tesauro, Encoding::Extended];
+
+    fn encoder(&self) -> &'static dyn Encoder {
+        match self {
+            Encoding::Standard => &StandardEncoder,
+            Encoding::Tesauro => &TesauroEncoder,
+            Encoding::Extended => &ExtendedEncoder,
+        }
+    }
+}
+
+impl Encoder for Encoding {
+    fn name(&self) -> &'static str {
+        self.encoder().name()
+    }
+
+    fn num_inputs(&self) -> usize {
+        self.encoder().num_inputs()
+    }
+
+    fn encode_into(&self, pos: &Position, inputs: &mut [f32]) {
+        self.encoder().encode_into(pos, inputs)
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Encoding::ALL
+            .into_iter()
+            .find(|encoding| encoding.name() == s)
+            .ok_or_else(|| format!("Unknown encoding '{}'", s))
+    }
+}
+
+pub struct StandardEncoder;
+
+impl Encoder for StandardEncoder {
+    fn name(&self) -> &'static str {
+        "standard"
+    }
+
+    fn num_inputs(&self) -> usize {
+        NUM_INPUTS
+    }
+
+    /// Same values as `Inputs::to_vec`.
+    fn encode_into(&self, pos: &Position, inputs: &mut [f32]) {
+        inputs[0] = pos.x_off() as f32;
+        inputs[1] = pos.o_off() as f32;
+        write_pip(&mut inputs[2..6], pos.x_bar());
+        write_pip(&mut inputs[102..106], pos.o_bar());
+        for i in 1..=24 {
+            let pip = pos.pip(i);
+            write_pip(&mut inputs[2 + 4 * i..6 + 4 * i], pip.max(0) as u8);
+            write_pip(&mut inputs[102 + 4 * i..106 + 4 * i], (-pip).max(0) as u8);
+        }
+    }
+}
+
+/// Four units per point and player, the last one for checkers beyond three,
+/// then checkers on the bar and borne off, and two units for the player on roll.
+pub struct TesauroEncoder;
+
+impl Encoder for TesauroEncoder {
+    fn name(&self) -> &'static str {
+        "tesauro"
+    }
+
+    fn num_inputs(&self) -> usize {
+        198
+    }
+
+    fn encode_into(&self, pos: &Position, inputs: &mut [f32]) {
+        let players = [
+            (1, pos.x_bar(), pos.x_off()),
+            (-1, pos.o_bar(), pos.o_off()),
+        ];
+        for (player, (sign, bar, off)) in players.into_iter().enumerate() {
+            let inputs = &mut inputs[98 * player..98 * (player + 1)];
+            for point in 1..=24 {
+                let n = (sign * pos.pip(point)).max(0) as f32;
+                let unit = &mut inputs[4 * (point - 1)..4 * point];
+                unit[0] = (n >= 1.0) as u8 as f32;
+                unit[1] = (n >= 2.0) as u8 as f32;
+                unit[2] = (n >= 3.0) as u8 as f32;
+                unit[3] = ((n - 3.0) / 2.0).max(0.0);
+            }
+            inputs[96] = bar as f32 / 2.0;
+            inputs[97] = off as f32 / 15.0;
+        }
+        // x is always on roll
+        inputs[196] = 1.0;
+        inputs[197] = 0.0;
+    }
+}
+
+/// `StandardEncoder` followed by pip counts, blot exposure, prime length and escapes,
+/// first for x and then for o.
+pub struct ExtendedEncoder;
+
+impl Encoder for ExtendedEncoder {
+    fn name(&self) -> &'static str {
+        "extended"
+    }
+
+    fn num_inputs(&self) -> usize {
+        NUM_INPUTS + 8
+    }
+
+    fn encode_into(&self, pos: &Position, inputs: &mut [f32]) {
+        StandardEncoder.encode_into(pos, &mut inputs[..NUM_INPUTS]);
+        let flipped = pos.flip();
+        for (features, pos) in inputs[NUM_INPUTS..]
+            .chunks_exact_mut(4)
+            .zip([pos, &flipped])
+        {
+            features[0] = pip_count(pos) as f32 / 167.0;
+            features[1] = shots(pos) as f32 / 36.0;
+            features[2] = (longest_prime(pos) as f32 / 6.0).min(1.0);
+            features[3] = escapes(pos) as f32 / 36.0;
+        }
+    }
+}
+
+/// Distances a single roll can move one checker, the first three or all four values.
+fn distances(die1: i8, die2: i8) -> ([i8; 4], usize) {
+    if die1 == die2 {
+        ([die1, 2 * die1, 3 * die1, 4 * die1], 4)
+    } else {
+        ([die1, die2, die1 + die2, 0], 3)
+    }
+}
+
+/// Pips x needs to bear off all checkers.
+fn pip_count(pos: &Position) -> u32 {
+    let board: u32 = (1..=24).map(|i| i as u32 * pos.pip(i).max(0) as u32).sum();
+    board + 25 * pos.x_bar() as u32
+}
+
+/// Rolls out of 36 with which o can hit a blot of x, blocked points are ignored.
+fn shots(pos: &Position) -> u32 {
+    // Fixed size buffers, encoding a position doesn't allocate.
+    let mut blots = [0_i8; 24];
+    let mut num_blots = 0;
+    let mut hitters = [0_i8; 25];
+    let mut num_hitters = 0;
+    for i in 1..=24 {
+        match pos.pip(i) {
+            1 => {
+                blots[num_blots] = i as i8;
+                num_blots += 1;
+            }
+            pip if pip < 0 => {
+                hitters[num_hitters] = i as i8;
+                num_hitters += 1;
+            }
+            _ => {}
+        }
+    }
+    if pos.o_bar() > 0 {
+        hitters[num_hitters] = 0;
+        num_hitters += 1;
+    }
+    let (blots, hitters) = (&blots[..num_blots], &hitters[..num_hitters]);
+    let mut count = 0;
+    for die1 in 1..=6 {
+        for die2 in 1..=6 {
+            let (distances, len) = distances(die1, die2);
+            let distances = &distances[..len];
+            let hit = blots.iter().any(|blot| {
+                hitters
+                    .iter()
+                    .any(|hitter| distances.contains(&(blot - hitter)))
+            });
+            count += hit as u32;
+        }
+    }
+    count
+}
+
+/// Most consecutive points made by x.
+fn longest_prime(pos: &Position) -> u32 {
+    let mut longest = 0;
+    let mut current = 0;
+    for i in 1..=24 {
+        if pos.pip(i) >= 2 {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+/// Rolls out of 36 with which the rearmost checker of x can move with at least one die.
+fn escapes(pos: &Position) -> u32 {
+    let rearmost = if pos.x_bar() > 0 {
+        25
+    } else {
+        match (1..=24).rev().find(|&i| pos.pip(i) > 0) {
+            Some(i) => i as i8,
+            None => return 36,
+        }
+    };
+    let open = |die: i8| {
+        let target = rearmost - die;
+        target < 1 || pos.pip(target as usize) >= -1
+    };
+    let mut count = 0;
+    for die1 in 1..=6 {
+        for die2 in 1..=6 {
+            count += (open(die1) || open(die2)) as u32;
+        }
+    }
+    count
+}
+
+/// The four values of `PipInput::from_pip`.
+fn write_pip(inputs: &mut [f32], pip: u8) {
+    let (p1, p2, p3, p4) = match pip {
+        0 => (0, 0, 0, 0),
+        1 => (1, 0, 0, 0),
+        2 => (0, 1, 0, 0),
+        p => (0, 0, 1, p - 3),
+    };
+    inputs[0] = p1 as f32;
+    inputs[1] = p2 as f32;
+    inputs[2] = p3 as f32;
+    inputs[3] = p4 as f32;
+}
+
 struct PipInput {
     p1: u8,
     p2: u8,
@@ -129,8 +397,11 @@ impl PipInput {
 
 #[cfg(test)]
 mod tests {
-    use crate::inputs::{Inputs, NUM_INPUTS};
+    use crate::inputs::{
+        escapes, longest_prime, pip_count, shots, Encoder, Encoding, Inputs, NUM_INPUTS,
+    };
     use bkgm::{pos, Position, O_BAR};
+    use std::str::FromStr;
 
     // #[test]
     // fn inputs_display() {
@@ -165,4 +436,59 @@ mod tests {
     fn no_empty_column_in_header() {
         assert_eq!(Inputs::csv_header().matches(";;").count(), 0)
     }
+
+    #[test]
+    fn encodings_have_their_number_of_inputs() {
+        let pos = pos!(x 25:1, 6:5, 1:3; o 0:2, 12:4, 24:1);
+        for encoding in Encoding::ALL {
+            assert_eq!(encoding.encode(&pos).len(), encoding.num_inputs());
+            assert_eq!(Encoding::from_str(encoding.name()), Ok(encoding));
+        }
+    }
+
+    #[test]
+    fn standard_encoder_matches_inputs() {
+        let positions = [
+            pos!(x 24:2, 13:5, 8:3, 6:5; o 1:2, 12:5, 17:3, 19:5),
+            pos!(x 25:1, 6:5, 1:3; o 0:2, 12:4, 24:1),
+            pos!(x 24:2, 13:5, 8:3, 6:5; o 1:2, 12:5, 17:3, 19:5).flip(),
+        ];
+        for pos in positions {
+            let inputs = Inputs::from_position(&pos).to_vec();
+            assert_eq!(Encoding::Standard.encode(&pos), inputs);
+            assert_eq!(Encoding::Extended.encode(&pos)[..NUM_INPUTS], inputs);
+        }
+    }
+
+    #[test]
+    fn tesauro() {
+        let inputs = Encoding::Tesauro.encode(&pos!(x 25:1, 24:5; o 1:2));
+        // x on 24
+        assert_eq!(inputs[92..96], [1.0, 1.0, 1.0, 1.0]);
+        // x bar and off
+        assert_eq!(inputs[96..98], [0.5, 9.0 / 15.0]);
+        // o on 1
+        assert_eq!(inputs[98..102], [1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(inputs[194..], [0.0, 13.0 / 15.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn blot_exposure_and_pips() {
+        let pos = pos!(x 6:1; o 3:1);
+        assert_eq!(pip_count(&pos), 6);
+        assert_eq!(pip_count(&pos.flip()), 22);
+        // any 3, 1-2, 2-1 and 1-1
+        assert_eq!(shots(&pos), 14);
+        assert_eq!(shots(&pos.flip()), 14);
+    }
+
+    #[test]
+    fn prime_and_escapes() {
+        let pos = pos!(x 24:1; o 18:2, 20:2, 21:2, 22:2, 23:2);
+        assert_eq!(longest_prime(&pos), 0);
+        assert_eq!(longest_prime(&pos.flip()), 4);
+        // only a 5 leaves the 24 point
+        assert_eq!(escapes(&pos), 11);
+        assert_eq!(escapes(&pos.flip()), 36);
+    }
 }
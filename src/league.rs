@@ -0,0 +1,99 @@
+//! Opponents for `TDTrainer::train_against`: a fixed evaluator or a league of past checkpoints.
+
+use crate::model::TDNetwork;
+use burn::tensor::backend::Backend;
+use std::path::{Path, PathBuf};
+
+/// Every training game is played against one member, sampled uniformly.
+/// A fixed opponent such as `PubEval` is a league of one.
+pub struct League<E> {
+    members: Vec<E>,
+}
+
+impl<E> League<E> {
+    pub fn new(members: Vec<E>) -> Result<Self, String> {
+        if members.is_empty() {
+            return Err("A league needs at least one member".to_string());
+        }
+        Ok(Self { members })
+    }
+
+    pub fn single(member: E) -> Self {
+        Self {
+            members: vec![member],
+        }
+    }
+
+    pub fn members(&self) -> &[E] {
+        &self.members
+    }
+
+    /// The opponent of game `episode`, the same one for the same seed.
+    pub fn pick(&self, seed: u64, episode: usize) -> &E {
+        let mut rng =
+            fastrand::Rng::with_seed(seed ^ (episode as u64).wrapping_mul(0xbf58_476d_1ce4_e5b9));
+        &self.members[rng.usize(..self.members.len())]
+    }
+}
+
+impl<B: Backend, M: TDNetwork<B>> League<M> {
+    /// Loads frozen networks from checkpoints, such as those of `checkpoints`.
+    pub fn load(paths: &[PathBuf], device: &B::Device) -> Result<Self, String> {
+        let members = paths
+            .iter()
+            .map(|path| M::load_checkpoint(path, device).map(|(model, _)| model))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(members)
+    }
+}
+
+/// The checkpoints `TDTrainer` saved in `dir`, `games-{episode}`, oldest first.
+/// The paths have no extension, like the ones the models are loaded from.
+pub fn checkpoints(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut checkpoints: Vec<(usize, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let stem = path.file_stem()?.to_str()?;
+            let episode = stem.strip_prefix("games-")?.parse().ok()?;
+            Some((episode, dir.join(stem)))
+        })
+        .collect();
+    checkpoints.sort();
+    checkpoints.dedup();
+    Ok(checkpoints.into_iter().map(|(_, path)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::league::{checkpoints, League};
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn picks_are_repeatable() {
+        let league = League::new(vec![0, 1, 2, 3]).unwrap();
+        let picks: Vec<usize> = (0..100).map(|ep| *league.pick(7, ep)).collect();
+        assert_eq!(
+            picks,
+            (0..100).map(|ep| *league.pick(7, ep)).collect::<Vec<_>>()
+        );
+        for member in 0..4 {
+            assert!(picks.contains(&member));
+        }
+        assert!(League::<usize>::new(vec![]).is_err());
+    }
+
+    #[test]
+    fn checkpoints_in_episode_order() {
+        let dir = TestDir::new("league");
+        for name in ["games-1000.mpk", "games-200.mpk", "best.mpk", "metrics.csv"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        assert_eq!(
+            checkpoints(dir.path()).unwrap(),
+            vec![dir.join("games-200"), dir.join("games-1000")]
+        );
+    }
+}
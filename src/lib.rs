@@ -1,8 +1,32 @@
+pub mod backend;
+pub mod benchmark;
+pub mod checkpoint;
+pub mod cpu;
+pub mod cube;
+pub mod dataset;
 pub mod dicegen;
+pub mod display;
 pub mod duel;
+pub mod engine;
 pub mod evaluator;
+pub mod exploration;
+pub mod external;
+pub mod fibs;
 pub mod fstate;
+pub mod generate;
 pub mod inputs;
+pub mod league;
+pub mod metrics;
 pub mod model;
+pub mod multinet;
+pub mod notation;
 pub mod probabilities;
+pub mod rollout;
+pub mod search;
+pub mod server;
+pub mod start;
+pub mod supervised;
+#[cfg(test)]
+mod test_dir;
 pub mod train;
+pub mod weights;
@@ -0,0 +1,269 @@
+//! Metrics which `TDTrainer` writes while training, one row per interval.
+
+use crate::benchmark::Opponent;
+use bkgm::GameResult;
+use burn::{
+    config::Config,
+    module::{Module, ModuleVisitor, ParamId},
+    tensor::{backend::Backend, Data, Tensor},
+};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+#[derive(Config, Debug, Copy, PartialEq, Eq)]
+pub enum MetricsFormat {
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+impl MetricsFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MetricsFormat::Csv => "csv",
+            MetricsFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+impl FromStr for MetricsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(MetricsFormat::Csv),
+            "jsonl" => Ok(MetricsFormat::Jsonl),
+            _ => Err(format!(
+                "Unknown metrics format '{}', expected csv or jsonl",
+                s
+            )),
+        }
+    }
+}
+
+pub enum MetricsWriter {
+    Csv(csv::Writer<File>),
+    Jsonl(File),
+}
+
+impl MetricsWriter {
+    /// Appends to `dir/name.csv` or `dir/name.jsonl`, so resumed runs continue their files.
+    /// The CSV header is only written to new files.
+    pub fn open(dir: &Path, name: &str, format: MetricsFormat) -> Result<Self, String> {
+        let path = dir.join(format!("{}.{}", name, format.extension()));
+        let exists = path.exists();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Ok(match format {
+            MetricsFormat::Csv => MetricsWriter::Csv(
+                csv::WriterBuilder::new()
+                    .has_headers(!exists)
+                    .from_writer(file),
+            ),
+            MetricsFormat::Jsonl => MetricsWriter::Jsonl(file),
+        })
+    }
+
+    /// Writes and flushes one row.
+    pub fn write<T: Serialize>(&mut self, row: &T) -> Result<(), String> {
+        let error = |e: &dyn std::fmt::Display| format!("Failed to write metrics: {}", e);
+        match self {
+            MetricsWriter::Csv(writer) => {
+                writer.serialize(row).map_err(|e| error(&e))?;
+                writer.flush().map_err(|e| error(&e))
+            }
+            MetricsWriter::Jsonl(file) => {
+                let mut line = serde_json::to_string(row).map_err(|e| error(&e))?;
+                line.push('\n');
+                file.write_all(line.as_bytes()).map_err(|e| error(&e))
+            }
+        }
+    }
+}
+
+/// One row of `metrics.csv`. Equities are missing if there was no benchmark in the interval.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MetricsRow {
+    pub episode: usize,
+    pub games: usize,
+    pub mean_abs_td_error: f32,
+    pub mean_game_length: f32,
+    /// Share of self-play games which ended with a gammon or backgammon.
+    pub gammon_rate: f32,
+    /// L2 norm of all parameters.
+    pub weight_norm: f32,
+    pub episodes_per_sec: f32,
+    pub random_equity: Option<f32>,
+    pub pubeval_equity: Option<f32>,
+    pub hyper_equity: Option<f32>,
+    pub best_equity: Option<f32>,
+}
+
+/// What happened since the last row.
+pub struct TrainingStats {
+    games: usize,
+    moves: usize,
+    gammons: usize,
+    td_error_sum: f64,
+    td_errors: usize,
+    benchmarks: Vec<(Opponent, f32)>,
+    start: Instant,
+}
+
+impl Default for TrainingStats {
+    fn default() -> Self {
+        Self {
+            games: 0,
+            moves: 0,
+            gammons: 0,
+            td_error_sum: 0.0,
+            td_errors: 0,
+            benchmarks: Vec::new(),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl TrainingStats {
+    pub fn add_td_errors(&mut self, td_errors: &[f32]) {
+        self.td_error_sum += td_errors.iter().map(|e| e.abs() as f64).sum::<f64>();
+        self.td_errors += td_errors.len();
+    }
+
+    pub fn add_game(&mut self, moves: usize, result: GameResult) {
+        self.games += 1;
+        self.moves += moves;
+        if !matches!(result, GameResult::WinNormal | GameResult::LoseNormal) {
+            self.gammons += 1;
+        }
+    }
+
+    pub fn add_benchmark(&mut self, opponent: Opponent, equity: f32) {
+        self.benchmarks.push((opponent, equity));
+    }
+
+    /// The row for the games so far, then starts the next interval.
+    pub fn take_row(&mut self, episode: usize, weight_norm: f32) -> MetricsRow {
+        let games = self.games.max(1) as f32;
+        let equity = |opponent| {
+            self.benchmarks
+                .iter()
+                .rev()
+                .find(|(o, _)| *o == opponent)
+                .map(|(_, equity)| *equity)
+        };
+        let row = MetricsRow {
+            episode,
+            games: self.games,
+            mean_abs_td_error: (self.td_error_sum / self.td_errors.max(1) as f64) as f32,
+            mean_game_length: self.moves as f32 / games,
+            gammon_rate: self.gammons as f32 / games,
+            weight_norm,
+            episodes_per_sec: self.games as f32 / self.start.elapsed().as_secs_f32(),
+            random_equity: equity(Opponent::Random),
+            pubeval_equity: equity(Opponent::PubEval),
+            hyper_equity: equity(Opponent::Hyper),
+            best_equity: equity(Opponent::Best),
+        };
+        *self = Self::default();
+        row
+    }
+}
+
+struct SquaredNorm {
+    sum: f64,
+}
+
+impl<B: Backend> ModuleVisitor<B> for SquaredNorm {
+    fn visit<const D: usize>(&mut self, _id: &ParamId, tensor: &Tensor<B, D>) {
+        let squares: Data<f32, 1> = (tensor.clone() * tensor.clone())
+            .sum()
+            .into_data()
+            .convert();
+        self.sum += squares.value[0] as f64;
+    }
+}
+
+/// L2 norm of all parameters of `model`.
+pub fn weight_norm<B: Backend, M: Module<B>>(model: &M) -> f32 {
+    let mut norm = SquaredNorm { sum: 0.0 };
+    model.visit(&mut norm);
+    norm.sum.sqrt() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{device, DefaultBackend};
+    use crate::benchmark::Opponent;
+    use crate::metrics::{weight_norm, MetricsFormat, MetricsWriter, TrainingStats};
+    use crate::model::{ModelConfig, TDModel};
+    use crate::test_dir::TestDir;
+    use crate::weights::Weights;
+    use bkgm::GameResult;
+
+    #[test]
+    fn stats() {
+        let mut stats = TrainingStats::default();
+        stats.add_td_errors(&[0.5, -0.25]);
+        stats.add_td_errors(&[-0.75, 0.5]);
+        stats.add_game(30, GameResult::WinNormal);
+        stats.add_game(20, GameResult::LoseGammon);
+        stats.add_benchmark(Opponent::PubEval, 0.25);
+
+        let row = stats.take_row(1000, 2.0);
+        assert_eq!(row.episode, 1000);
+        assert_eq!(row.games, 2);
+        assert_eq!(row.mean_abs_td_error, 0.5);
+        assert_eq!(row.mean_game_length, 25.0);
+        assert_eq!(row.gammon_rate, 0.5);
+        assert_eq!(row.pubeval_equity, Some(0.25));
+        assert_eq!(row.random_equity, None);
+
+        let row = stats.take_row(2000, 2.0);
+        assert_eq!(row.games, 0);
+        assert_eq!(row.pubeval_equity, None);
+    }
+
+    #[test]
+    fn norm_of_all_weights() {
+        let model =
+            TDModel::<DefaultBackend>::new(ModelConfig::new().with_neurons(4), &device(true));
+        let expected: f32 = Weights::from_model(&model)
+            .layers
+            .iter()
+            .flat_map(|layer| layer.weights.iter().chain(&layer.bias))
+            .map(|w| w * w)
+            .sum::<f32>()
+            .sqrt();
+        assert!((weight_norm(&model) - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn csv_and_jsonl() {
+        let dir = TestDir::new("metrics");
+        for format in [MetricsFormat::Csv, MetricsFormat::Jsonl] {
+            let row = TrainingStats::default().take_row(5, 1.5);
+            for _ in 0..2 {
+                let mut writer = MetricsWriter::open(dir.path(), "metrics", format).unwrap();
+                writer.write(&row).unwrap();
+            }
+        }
+        let csv = std::fs::read_to_string(dir.join("metrics.csv")).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("episode,games,mean_abs_td_error"));
+        assert!(lines[1].starts_with("5,0,0.0,"));
+        let jsonl = std::fs::read_to_string(dir.join("metrics.jsonl")).unwrap();
+        let lines: Vec<&str> = jsonl.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("{\"episode\":5,"));
+        assert!(lines[1].contains("\"random_equity\":null"));
+    }
+}
@@ -1,8 +1,12 @@
 use std::path::PathBuf;
+use std::str::FromStr;
 
-use crate::{evaluator::Evaluator, fstate::FState, inputs::Inputs};
-use bkgm::GameState::{GameOver, Ongoing};
-use bkgm::{dice::ALL_21, position, Dice, GameResult, Position, State};
+use crate::checkpoint::CheckpointInfo;
+use crate::evaluator::{Evaluator, PositionEvaluator};
+use crate::inputs::{Encoder, Encoding};
+use crate::search::{self, BatchEvaluator};
+use crate::{fstate::FState, probabilities::Probabilities};
+use bkgm::{position, Dice, GameResult, Position, State};
 use burn::config::Config;
 use burn::{
     data,
@@ -11,12 +15,12 @@ use burn::{
         self,
         loss::{MSELoss, Reduction::Mean},
     },
-    record::{NoStdTrainingRecorder, Recorder},
+    record::{NoStdTrainingRecorder, Record, Recorder, RecorderError},
     tensor::{
         self,
-        activation::sigmoid,
+        activation::{relu, sigmoid},
         backend::{AutodiffBackend, Backend},
-        Data, Tensor,
+        Data, Shape, Tensor,
     },
     train::RegressionOutput,
 };
@@ -29,17 +33,135 @@ pub struct ModelConfig {
     pub layers: usize,
     #[config(default = 160)]
     pub neurons: usize,
+    /// Width of each hidden layer, overrides `layers` and `neurons` if not empty.
+    #[config(default = "Vec::new()")]
+    pub widths: Vec<usize>,
+    #[config(default = "Activation::Sigmoid")]
+    pub activation: Activation,
+    #[config(default = "Encoding::Standard")]
+    pub encoding: Encoding,
     #[config(default = 1)]
     pub nply: usize,
+    /// 5 for the GNU style probabilities (win, win_g, win_b, lose_g, lose_b), 1 for win only.
+    #[config(default = 5)]
+    pub outputs: usize,
+}
+
+impl ModelConfig {
+    pub fn hidden_widths(&self) -> Vec<usize> {
+        if self.widths.is_empty() {
+            vec![self.neurons; self.layers]
+        } else {
+            self.widths.clone()
+        }
+    }
+}
+
+/// Activation of the hidden layers, the outputs always use a sigmoid.
+#[derive(Config, Debug, Copy, PartialEq, Eq)]
+pub enum Activation {
+    Sigmoid,
+    Relu,
+    Tanh,
+}
+
+burn::constant!(Activation);
+
+impl FromStr for Activation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sigmoid" => Ok(Activation::Sigmoid),
+            "relu" => Ok(Activation::Relu),
+            "tanh" => Ok(Activation::Tanh),
+            _ => Err(format!(
+                "Unknown activation '{}', expected sigmoid, relu or tanh",
+                s
+            )),
+        }
+    }
+}
+
+impl Activation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Activation::Sigmoid => "sigmoid",
+            Activation::Relu => "relu",
+            Activation::Tanh => "tanh",
+        }
+    }
+
+    fn forward<B: Backend>(&self, x: Tensor<B, 2>) -> Tensor<B, 2> {
+        match self {
+            Activation::Sigmoid => sigmoid(x),
+            Activation::Relu => relu(x),
+            Activation::Tanh => x.tanh(),
+        }
+    }
 }
 
 #[derive(Module, Debug)]
 pub struct TDModel<B: Backend> {
-    // layers: usize,
-    // neurons: usize,
-    // nply: usize,
     fc1: nn::Linear<B>,
     output: nn::Linear<B>,
+    /// Hidden layers after `fc1`. Declared after `output` so that legacy records end too early
+    /// to be misread as new ones.
+    hidden: Vec<nn::Linear<B>>,
+    activation: Activation,
+    encoding: Encoding,
+}
+
+/// Networks which `TDTrainer` can train.
+pub trait TDNetwork<B: Backend>: Module<B> {
+    /// Outputs for a single position, from the perspective of the player on roll.
+    /// Computed on the device of the parameters.
+    fn forward_pos<G: State>(&self, position: G) -> Tensor<B, 1>;
+
+    /// Training target for a finished game, in the same layout as the outputs.
+    fn from_result(&self, result: GameResult) -> Tensor<B, 1>;
+
+    /// Stored in checkpoints, so that they can't be loaded as a different type of network.
+    const KIND: &'static str;
+
+    /// The configuration which recreates this network, read from the shapes of its layers.
+    fn config(&self) -> ModelConfig;
+
+    /// Saves the weights together with `info` as JSON.
+    fn save(self, path: PathBuf, info: CheckpointInfo) -> Result<(), RecorderError>;
+
+    /// Loads a checkpoint written by `save`, together with what it says about its training.
+    fn load_checkpoint(
+        path: &PathBuf,
+        device: &B::Device,
+    ) -> Result<(Self, CheckpointInfo), String>;
+
+    /// Describes the network, training details are added by the caller.
+    fn checkpoint_info(&self) -> CheckpointInfo {
+        CheckpointInfo::new(Self::KIND.to_string(), self.config())
+    }
+}
+
+/// What `TDModel::save` writes. The `CheckpointInfo` comes after the weights,
+/// so that checkpoints without it fail to load as this instead of being misread.
+/// Checkpoints from before `CheckpointInfo` hold the name of the encoder instead.
+#[derive(Record)]
+pub struct TDModelFile<B: Backend> {
+    pub model: TDModelRecord<B>,
+    pub info: String,
+}
+
+/// Checkpoints from before configurable layers, a single sigmoid hidden layer.
+#[derive(Module, Debug)]
+struct LegacyTDModel<B: Backend> {
+    fc1: nn::Linear<B>,
+    output: nn::Linear<B>,
+}
+
+/// A layer with the shape of its record.
+fn linear_from<B: Backend>(record: nn::LinearRecord<B>) -> nn::Linear<B> {
+    let [inputs, outputs] = record.weight.shape().dims;
+    nn::LinearConfig::new(inputs, outputs).init_with(record)
 }
 
 impl<B: Backend> Default for TDModel<B> {
@@ -51,252 +173,192 @@ impl<B: Backend> Default for TDModel<B> {
 
 impl<B: Backend> TDModel<B> {
     pub fn new(config: ModelConfig, device: &B::Device) -> Self {
+        let widths = config.hidden_widths();
+        assert!(
+            !widths.is_empty(),
+            "The model needs at least one hidden layer"
+        );
         Self {
-            fc1: nn::LinearConfig::new(202, config.neurons).init(device),
-            output: nn::LinearConfig::new(config.neurons, 1).init(device),
+            fc1: nn::LinearConfig::new(config.encoding.num_inputs(), widths[0]).init(device),
+            hidden: widths
+                .windows(2)
+                .map(|w| nn::LinearConfig::new(w[0], w[1]).init(device))
+                .collect(),
+            output: nn::LinearConfig::new(widths[widths.len() - 1], config.outputs).init(device),
+            activation: config.activation,
+            encoding: config.encoding,
         }
     }
 
+    /// The number and shape of the layers are taken from the record, so single output checkpoints
+    /// still load, and `check_config` sees every layer the record has.
     pub fn new_from(config: ModelConfig, record: TDModelRecord<B>) -> Self {
-        // record.
         Self {
-            fc1: nn::LinearConfig::new(202, config.neurons).init_with(record.fc1),
-            output: nn::LinearConfig::new(config.neurons, 1).init_with(record.output),
+            fc1: linear_from(record.fc1),
+            hidden: record.hidden.into_iter().map(linear_from).collect(),
+            output: linear_from(record.output),
+            activation: config.activation,
+            encoding: config.encoding,
         }
     }
 
-    pub fn init_with(config: ModelConfig, device: B::Device, model_path: &PathBuf) -> Self {
-        let record = NoStdTrainingRecorder::new()
-            .load(model_path.into(), &device)
-            .expect("Failed to load model");
-        Self::new_from(config, record)
+    /// `layers` are the hidden layers followed by the output layer, as returned by `layers`.
+    pub(crate) fn from_layers(
+        mut layers: Vec<nn::Linear<B>>,
+        activation: Activation,
+        encoding: Encoding,
+    ) -> Self {
+        let output = layers.pop().expect("Missing output layer");
+        let mut hidden = layers.into_iter();
+        Self {
+            fc1: hidden.next().expect("Missing hidden layer"),
+            hidden: hidden.collect(),
+            output,
+            activation,
+            encoding,
+        }
     }
 
-    fn inputs(&self, position: &bkgm::Position) -> Data<f32, 1> {
-        Data::<f32, 1>::from(Inputs::from_position(position).to_vec().as_slice())
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
     }
 
-    pub fn input_tensor(&self, device: &B::Device, positions: Vec<Position>) -> Tensor<B, 2> {
-        let tensor_pos = positions
-            .iter()
-            .map(|item| self.inputs(&item))
-            .map(|data| Tensor::<B, 1>::from_data(data.convert(), device))
-            .map(|tensor| tensor.reshape([1, 202]))
-            .collect();
-
-        Tensor::cat(tensor_pos, 0)
+    /// Where the parameters are, all evaluations happen there.
+    pub fn device(&self) -> B::Device {
+        self.output.weight.device()
     }
 
-    fn forward(&self, input: Tensor<B, 2>) -> Tensor<B, 2> {
-        let x = self.fc1.forward(input);
-        let x = sigmoid(x);
-        let x = self.output.forward(x);
-        sigmoid(x)
+    pub fn activation(&self) -> Activation {
+        self.activation
     }
 
-    pub fn forward_pos<G: bkgm::State>(&self, position: G, device: &B::Device) -> Tensor<B, 1> {
-        let inputs = self.input_tensor(device, vec![position.position()]);
-        let output = self.forward(inputs);
-        output.reshape([1])
+    /// All layers in the order they are applied, ending with the output layer.
+    pub fn layers(&self) -> Vec<&nn::Linear<B>> {
+        let mut layers = vec![&self.fc1];
+        layers.extend(self.hidden.iter());
+        layers.push(&self.output);
+        layers
     }
 
-    pub fn result_value(&self, result: GameResult) -> f32 {
-        match result {
-            GameResult::WinNormal => 1.0,
-            GameResult::WinGammon => 1.0,
-            GameResult::WinBackgammon => 1.0,
-            GameResult::LoseNormal => 0.0,
-            GameResult::LoseGammon => 0.0,
-            GameResult::LoseBackgammon => 0.0,
-        }
+    pub fn num_outputs(&self) -> usize {
+        self.output.weight.shape().dims[1]
     }
 
-    pub fn from_result(&self, result: GameResult, device: &B::Device) -> Tensor<B, 1> {
-        let data = Data::<f32, 1>::from([self.result_value(result)].as_slice());
-        Tensor::<B, 1>::from_data(data.convert(), device)
+    /// Loads a checkpoint written by `TDNetwork::save`, the configuration is taken from it.
+    pub fn load(path: &PathBuf, device: &B::Device) -> Result<Self, String> {
+        <Self as TDNetwork<B>>::load_checkpoint(path, device).map(|(model, _)| model)
     }
 
-    fn finder<G: State + Send>(
-        &self,
-        maxer: bool,
-        pos: &FState<G>,
-        dice: &Dice,
-    ) -> (FState<G>, f32) {
-        let device = B::Device::default();
+    /// Fails if the layers don't have the shapes `config` describes.
+    pub(crate) fn check_config(&self, config: &ModelConfig) -> Result<(), String> {
+        let actual = self.config();
+        let inputs = self.fc1.weight.shape().dims[0];
+        if actual.widths != config.hidden_widths()
+            || actual.outputs != config.outputs
+            || inputs != config.encoding.num_inputs()
+        {
+            return Err(format!(
+                "Layers {} -> {:?} -> {} don't match the configuration {} -> {:?} -> {}",
+                inputs,
+                actual.widths,
+                actual.outputs,
+                config.encoding.num_inputs(),
+                config.hidden_widths(),
+                config.outputs
+            ));
+        }
+        Ok(())
+    }
 
-        let positions = pos.possible_positions(dice);
-
-        if pos.turn {
-            let inputs =
-                self.input_tensor(&device, positions.iter().map(|p| p.position()).collect());
-            let outputs = self.forward(inputs);
-
-            let data: Data<f32, 2> = outputs.into_data().convert();
-
-            if maxer {
-                positions
-                    .iter()
-                    .enumerate()
-                    .map(|(i, pos)| (*pos, data.value[i]))
-                    .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                    .unwrap()
-            } else {
-                positions
-                    .iter()
-                    .enumerate()
-                    .map(|(i, pos)| (*pos, data.value[i]))
-                    .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                    .unwrap()
-            }
-        } else {
-            let inputs = self.input_tensor(
-                &device,
-                positions.iter().map(|p| p.position().flip()).collect(),
-            );
-            let outputs = self.forward(inputs);
-
-            let data: Data<f32, 2> = outputs.into_data().convert();
-
-            if maxer {
-                positions
-                    .iter()
-                    .enumerate()
-                    .map(|(i, pos)| (*pos, data.value[i]))
-                    .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                    .unwrap()
-            } else {
-                positions
-                    .iter()
-                    .enumerate()
-                    .map(|(i, pos)| (*pos, data.value[i]))
-                    .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                    .unwrap()
+    /// Prefers the configuration stored in the checkpoint over `config`.
+    /// Also loads checkpoints which only name their encoder, those saved without an encoder,
+    /// which use `Encoding::Standard`, and those saved before the number of layers was configurable.
+    pub fn init_with(config: ModelConfig, device: B::Device, model_path: &PathBuf) -> Self {
+        let recorder = NoStdTrainingRecorder::new();
+        if let Ok(file) = recorder.load::<TDModelFile<B>>(model_path.into(), &device) {
+            let config = match CheckpointInfo::from_json(&file.info) {
+                Ok(info) => info.model,
+                Err(_) => {
+                    let encoding = Encoding::from_str(&file.info).expect("Failed to load model");
+                    config.with_encoding(encoding)
+                }
+            };
+            return Self::new_from(config, file.model);
+        }
+        let config = config.with_encoding(Encoding::Standard);
+        match recorder.load::<TDModelRecord<B>>(model_path.into(), &device) {
+            Ok(record) => Self::new_from(config, record),
+            Err(_) => {
+                let legacy: LegacyTDModelRecord<B> = recorder
+                    .load(model_path.into(), &device)
+                    .expect("Failed to load model");
+                Self {
+                    fc1: linear_from(legacy.fc1),
+                    hidden: Vec::new(),
+                    output: linear_from(legacy.output),
+                    activation: Activation::Sigmoid,
+                    encoding: Encoding::Standard,
+                }
             }
         }
     }
 
-    fn nply<G: State + Send>(
+    /// Encodes all positions into one buffer, which becomes a single `[positions, inputs]` tensor.
+    pub fn input_tensor<G: State>(&self, device: &B::Device, positions: &[G]) -> Tensor<B, 2> {
+        self.encode(device, positions.iter().map(|pos| pos.position()))
+    }
+
+    /// Same as `input_tensor` for bare positions, such as those of a dataset.
+    pub fn position_tensor(&self, device: &B::Device, positions: &[Position]) -> Tensor<B, 2> {
+        self.encode(device, positions.iter().copied())
+    }
+
+    fn encode(
         &self,
-        depth: usize,
-        maxer: bool,
-        pos: &FState<G>,
-        dice: &Dice,
-    ) -> (FState<G>, f32) {
-        if depth == 1 {
-            return self.finder(maxer, pos, dice);
+        device: &B::Device,
+        positions: impl ExactSizeIterator<Item = Position>,
+    ) -> Tensor<B, 2> {
+        let num_inputs = self.encoding.num_inputs();
+        let len = positions.len();
+        let mut inputs = vec![0.0; len * num_inputs];
+        for (pos, inputs) in positions.zip(inputs.chunks_exact_mut(num_inputs)) {
+            self.encoding.encode_into(&pos, inputs);
         }
+        let data = Data::new(inputs, Shape::new([len, num_inputs]));
+        Tensor::from_data(data.convert(), device)
+    }
 
-        if pos.turn {
-            if maxer {
-                pos.possible_positions(dice)
-                    .iter()
-                    .map(|p| match p.game_state() {
-                        GameOver(result) => (
-                            *p,
-                            if !pos.turn {
-                                self.result_value(result)
-                            } else {
-                                1.0 - self.result_value(result)
-                            },
-                        ), // check p.turn
-                        Ongoing => {
-                            let mut nprobs = 0.0;
-                            let mut total = 0.0;
-                            for (dice, n) in ALL_21 {
-                                let (_, v) = self.nply(depth - 1, !maxer, p, &dice);
-                                total += v;
-                                nprobs += n;
-                            }
-                            (*p, total / nprobs)
-                        }
-                    })
-                    .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                    .unwrap()
-            } else {
-                pos.possible_positions(dice)
-                    .iter()
-                    .map(|p| match p.game_state() {
-                        GameOver(result) => (
-                            *p,
-                            if !pos.turn {
-                                self.result_value(result)
-                            } else {
-                                1.0 - self.result_value(result)
-                            },
-                        ), // check p.turn
-                        Ongoing => {
-                            let mut nprobs = 0.0;
-                            let mut total = 0.0;
-                            for (dice, n) in ALL_21 {
-                                let (_, v) = self.nply(depth - 1, !maxer, p, &dice);
-                                total += v;
-                                nprobs += n;
-                            }
-                            (*p, total / nprobs)
-                        }
-                    })
-                    .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                    .unwrap()
-            }
-        } else {
-            if maxer {
-                pos.possible_positions(dice)
-                    .iter()
-                    .map(|p| match p.game_state() {
-                        GameOver(result) => (
-                            *p,
-                            if !pos.turn {
-                                self.result_value(result)
-                            } else {
-                                1.0 - self.result_value(result)
-                            },
-                        ), // check p.turn
-                        Ongoing => {
-                            let mut nprobs = 0.0;
-                            let mut total = 0.0;
-                            for (dice, n) in ALL_21 {
-                                let (_, v) = self.nply(depth - 1, !maxer, p, &dice);
-                                total += v;
-                                nprobs += n;
-                            }
-                            (*p, total / nprobs)
-                        }
-                    })
-                    .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                    .unwrap()
-            } else {
-                pos.possible_positions(dice)
-                    .iter()
-                    .map(|p| match p.game_state() {
-                        GameOver(result) => (
-                            *p,
-                            if !pos.turn {
-                                self.result_value(result)
-                            } else {
-                                1.0 - self.result_value(result)
-                            },
-                        ), // check p.turn
-                        Ongoing => {
-                            let mut nprobs = 0.0;
-                            let mut total = 0.0;
-                            for (dice, n) in ALL_21 {
-                                let (_, v) = self.nply(depth - 1, !maxer, p, &dice);
-                                total += v;
-                                nprobs += n;
-                            }
-                            (*p, total / nprobs)
-                        }
-                    })
-                    .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                    .unwrap()
-            }
+    pub(crate) fn forward(&self, input: Tensor<B, 2>) -> Tensor<B, 2> {
+        let mut x = self.activation.forward(self.fc1.forward(input));
+        for layer in &self.hidden {
+            x = self.activation.forward(layer.forward(x));
         }
+        let x = self.output.forward(x);
+        sigmoid(x)
+    }
+
+    /// Training target for a finished game, in the same layout as the outputs.
+    pub fn result_value(&self, result: GameResult) -> Vec<f32> {
+        self.target(&Probabilities::from_result(&result))
+    }
+
+    /// Training target for cubeless probabilities, in the same layout as the outputs.
+    pub fn target(&self, probs: &Probabilities) -> Vec<f32> {
+        probs.to_gnu()[..self.num_outputs()].to_vec()
+    }
+
+    pub(crate) fn probabilities(&self, outputs: Tensor<B, 2>) -> Vec<Probabilities> {
+        let data: Data<f32, 2> = outputs.into_data().convert();
+        data.value
+            .chunks(self.num_outputs())
+            .map(probabilities_from_outputs)
+            .collect()
     }
 }
 
 impl<G: State + Send, B: Backend> Evaluator<FState<G>> for TDModel<B> {
     fn best_position(&self, pos: &FState<G>, dice: &Dice) -> FState<G> {
-        self.nply(1, true, pos, dice).0
+        search::best_position(self, 1, pos, dice)
     }
 
     // fn best_position(&self, pos: &FState<G>, dice: &Dice) -> FState<G> {
@@ -337,3 +399,247 @@ impl<G: State + Send, B: Backend> Evaluator<FState<G>> for TDModel<B> {
     //     }
     // }
 }
+
+impl<G: State + Send, B: Backend> PositionEvaluator<FState<G>> for TDModel<B> {
+    fn eval(&self, pos: &FState<G>) -> Probabilities {
+        self.eval_batch(&[*pos])[0]
+    }
+
+    fn eval_batch(&self, positions: &[FState<G>]) -> Vec<Probabilities> {
+        self.evaluate(positions)
+    }
+
+    /// The same ranking as `best_position`, see `search::ranked_positions`.
+    fn ranked_positions(&self, pos: &FState<G>, dice: &Dice) -> Vec<(FState<G>, Probabilities)> {
+        search::ranked_positions(self, pos, dice)
+    }
+}
+
+impl<B: Backend> TDNetwork<B> for TDModel<B> {
+    fn forward_pos<G: State>(&self, position: G) -> Tensor<B, 1> {
+        let inputs = self.input_tensor(&self.device(), &[position]);
+        let output = self.forward(inputs);
+        output.reshape([self.num_outputs()])
+    }
+
+    fn from_result(&self, result: GameResult) -> Tensor<B, 1> {
+        let data = Data::<f32, 1>::from(self.result_value(result).as_slice());
+        Tensor::<B, 1>::from_data(data.convert(), &self.device())
+    }
+
+    const KIND: &'static str = "td";
+
+    fn config(&self) -> ModelConfig {
+        let mut widths = vec![self.fc1.weight.shape().dims[1]];
+        widths.extend(self.hidden.iter().map(|layer| layer.weight.shape().dims[1]));
+        ModelConfig::new()
+            .with_layers(widths.len())
+            .with_neurons(widths[0])
+            .with_widths(widths)
+            .with_activation(self.activation)
+            .with_encoding(self.encoding)
+            .with_outputs(self.num_outputs())
+    }
+
+    fn save(self, path: PathBuf, info: CheckpointInfo) -> Result<(), RecorderError> {
+        let file = TDModelFile {
+            model: self.into_record(),
+            info: info.to_json(),
+        };
+        NoStdTrainingRecorder::new().record(file, path)
+    }
+
+    fn load_checkpoint(
+        path: &PathBuf,
+        device: &B::Device,
+    ) -> Result<(Self, CheckpointInfo), String> {
+        let file = NoStdTrainingRecorder::new()
+            .load::<TDModelFile<B>>(path.into(), device)
+            .map_err(|e| format!("Failed to load {}: {:?}", path.display(), e))?;
+        let info = CheckpointInfo::from_json(&file.info).map_err(|_| {
+            format!(
+                "{} has no model configuration, load it with init_with",
+                path.display()
+            )
+        })?;
+        info.check(<Self as TDNetwork<B>>::KIND)?;
+        let model = Self::new_from(info.model.clone(), file.model);
+        model.check_config(&info.model)?;
+        Ok((model, info))
+    }
+}
+
+impl<B: Backend> BatchEvaluator for TDModel<B> {
+    fn evaluate<G: State>(&self, positions: &[G]) -> Vec<Probabilities> {
+        let inputs = self.input_tensor(&self.device(), positions);
+        self.probabilities(self.forward(inputs))
+    }
+}
+
+/// Single output nets only predict winning, gammons are then counted as plain wins.
+/// Five outputs are clamped so that gammons never exceed wins and backgammons never exceed gammons.
+pub(crate) fn probabilities_from_outputs(outputs: &[f32]) -> Probabilities {
+    match *outputs {
+        [win] => Probabilities {
+            win_n: win,
+            win_g: 0.0,
+            win_b: 0.0,
+            lose_n: 1.0 - win,
+            lose_g: 0.0,
+            lose_b: 0.0,
+        },
+        [win, win_g, win_b, lose_g, lose_b] => {
+            let win_g = win_g.min(win);
+            let lose_g = lose_g.min(1.0 - win);
+            Probabilities::from(&[win, win_g, win_b.min(win_g), lose_g, lose_b.min(lose_g)])
+        }
+        _ => panic!("Expected 1 or 5 outputs, got {}", outputs.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{device, DefaultBackend};
+    use crate::inputs::Encoding;
+    use crate::model::{
+        probabilities_from_outputs, Activation, LegacyTDModel, ModelConfig, TDModel, TDModelFile,
+        TDNetwork,
+    };
+    use crate::search::BatchEvaluator;
+    use crate::test_dir::TestDir;
+    use crate::train::TDConfig;
+    use bkgm::{Backgammon, GameResult, State};
+    use burn::module::Module;
+    use burn::nn::LinearConfig;
+    use burn::record::{NoStdTrainingRecorder, Recorder};
+
+    #[test]
+    fn evaluates_on_the_device_of_the_parameters() {
+        let device = device(true);
+        let model = TDModel::<DefaultBackend>::new(ModelConfig::new(), &device);
+        assert_eq!(model.device(), device);
+
+        let position = Backgammon::new();
+        assert_eq!(model.forward_pos(position).device(), device);
+        assert_eq!(model.from_result(GameResult::WinGammon).device(), device);
+        let probs = model.evaluate(&[position, position.flip()]);
+        assert_eq!(probs.len(), 2);
+        assert!((probs[0].to_slice().iter().sum::<f32>() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let device = device(true);
+        let config = ModelConfig::new()
+            .with_widths(vec![12, 6])
+            .with_activation(Activation::Relu)
+            .with_encoding(Encoding::Extended)
+            .with_outputs(1);
+        let model = TDModel::<DefaultBackend>::new(config, &device);
+        let info = model
+            .checkpoint_info()
+            .with_training(Some(TDConfig::new()))
+            .with_episode(3000);
+        let dir = TestDir::new("checkpoint-round-trip");
+        let path = dir.join("model");
+        let position = Backgammon::new();
+        let expected = model.evaluate(&[position]);
+        model.save(path.clone(), info).unwrap();
+
+        let (loaded, info) = TDModel::<DefaultBackend>::load_checkpoint(&path, &device).unwrap();
+        assert_eq!(info.episode, 3000);
+        assert_eq!(loaded.config().hidden_widths(), vec![12, 6]);
+        assert_eq!(loaded.activation(), Activation::Relu);
+        assert_eq!(loaded.encoding(), Encoding::Extended);
+        assert_eq!(loaded.num_outputs(), 1);
+        assert_eq!(loaded.evaluate(&[position]), expected);
+    }
+
+    #[test]
+    fn checkpoint_without_config() {
+        let device = device(true);
+        let model = TDModel::<DefaultBackend>::new(ModelConfig::new().with_neurons(8), &device);
+        let dir = TestDir::new("checkpoint-without-config");
+        let path = dir.join("model");
+        let file = TDModelFile {
+            model: model.into_record(),
+            info: "standard".to_string(),
+        };
+        NoStdTrainingRecorder::new()
+            .record(file, path.clone())
+            .unwrap();
+
+        assert!(TDModel::<DefaultBackend>::load(&path, &device).is_err());
+        let config = ModelConfig::new().with_neurons(8);
+        let model = TDModel::<DefaultBackend>::init_with(config, device, &path);
+        assert_eq!(model.config().hidden_widths(), vec![8]);
+    }
+
+    #[test]
+    fn layers_come_from_the_record() {
+        let config = ModelConfig::new().with_widths(vec![16, 8]);
+        let model = TDModel::<DefaultBackend>::new(config.clone(), &device(true));
+        let shallow = ModelConfig::new().with_neurons(16);
+        let loaded = TDModel::new_from(shallow.clone(), model.into_record());
+        assert_eq!(loaded.config().hidden_widths(), vec![16, 8]);
+        assert!(loaded.check_config(&shallow).is_err());
+        assert!(loaded.check_config(&config).is_ok());
+    }
+
+    #[test]
+    fn legacy_layers_come_from_the_record() {
+        let device = device(true);
+        let legacy = LegacyTDModel::<DefaultBackend> {
+            fc1: LinearConfig::new(202, 8).init(&device),
+            output: LinearConfig::new(8, 1).init(&device),
+        };
+        let dir = TestDir::new("legacy");
+        let path = dir.join("model");
+        NoStdTrainingRecorder::new()
+            .record(legacy.into_record(), path.clone())
+            .unwrap();
+
+        let model = TDModel::<DefaultBackend>::init_with(ModelConfig::new(), device, &path);
+        assert_eq!(model.config().hidden_widths(), vec![8]);
+        assert_eq!(model.num_outputs(), 1);
+    }
+
+    #[test]
+    fn hidden_widths() {
+        assert_eq!(ModelConfig::new().hidden_widths(), vec![160]);
+        let config = ModelConfig::new().with_layers(3).with_neurons(40);
+        assert_eq!(config.hidden_widths(), vec![40, 40, 40]);
+        let config = config.with_widths(vec![80, 20]);
+        assert_eq!(config.hidden_widths(), vec![80, 20]);
+    }
+
+    #[test]
+    fn single_output() {
+        let probs = probabilities_from_outputs(&[0.75]);
+        assert_eq!(probs.win_n, 0.75);
+        assert_eq!(probs.lose_n, 0.25);
+        assert_eq!(probs.equity(), 0.5);
+    }
+
+    #[test]
+    fn five_outputs() {
+        let probs = probabilities_from_outputs(&[0.5, 0.25, 0.125, 0.25, 0.0]);
+        assert_eq!(probs.win_n, 0.25);
+        assert_eq!(probs.win_g, 0.125);
+        assert_eq!(probs.win_b, 0.125);
+        assert_eq!(probs.lose_n, 0.25);
+        assert_eq!(probs.lose_g, 0.25);
+        assert_eq!(probs.equity(), 0.125);
+    }
+
+    #[test]
+    fn inconsistent_outputs_are_clamped() {
+        let probs = probabilities_from_outputs(&[0.5, 0.75, 0.875, 0.75, 0.875]);
+        assert_eq!(probs.win_n, 0.0);
+        assert_eq!(probs.win_g, 0.0);
+        assert_eq!(probs.win_b, 0.5);
+        assert_eq!(probs.lose_n, 0.0);
+        assert_eq!(probs.lose_g, 0.0);
+        assert_eq!(probs.lose_b, 0.5);
+    }
+}
@@ -0,0 +1,253 @@
+use std::path::PathBuf;
+
+use crate::checkpoint::CheckpointInfo;
+use crate::evaluator::{Evaluator, PositionEvaluator};
+use crate::inputs::Encoding;
+use crate::model::{ModelConfig, TDModel, TDNetwork};
+use crate::search::{self, BatchEvaluator};
+use crate::{fstate::FState, probabilities::Probabilities};
+use bkgm::position::{GamePhase, Phase};
+use bkgm::{Dice, GameResult, State};
+use burn::{
+    module::Module,
+    record::{NoStdTrainingRecorder, Record, Recorder, RecorderError},
+    tensor::{backend::Backend, Tensor},
+};
+use std::str::FromStr;
+
+/// One `TDModel` for contact positions and one for races, chosen by `State::phase`.
+/// Finished games are evaluated by the race net.
+///
+/// When trained by `TDTrainer` a position only produces gradients for its own sub-net,
+/// both sub-nets are saved together as one record.
+#[derive(Module, Debug)]
+pub struct MultiNet<B: Backend> {
+    contact: TDModel<B>,
+    race: TDModel<B>,
+}
+
+/// What `MultiNet::save` writes, the same layout as `TDModelFile`.
+#[derive(Record)]
+pub struct MultiNetFile<B: Backend> {
+    pub model: MultiNetRecord<B>,
+    pub info: String,
+}
+
+impl<B: Backend> MultiNet<B> {
+    pub fn new(config: ModelConfig, device: &B::Device) -> Self {
+        Self {
+            contact: TDModel::new(config.clone(), device),
+            race: TDModel::new(config, device),
+        }
+    }
+
+    pub fn new_from(config: ModelConfig, record: MultiNetRecord<B>) -> Self {
+        Self {
+            contact: TDModel::new_from(config.clone(), record.contact),
+            race: TDModel::new_from(config, record.race),
+        }
+    }
+
+    /// Loads a checkpoint written by `TDNetwork::save`, the configuration is taken from it.
+    pub fn load(path: &PathBuf, device: &B::Device) -> Result<Self, String> {
+        <Self as TDNetwork<B>>::load_checkpoint(path, device).map(|(model, _)| model)
+    }
+
+    /// Prefers the configuration stored in the checkpoint over `config`.
+    pub fn init_with(config: ModelConfig, device: B::Device, model_path: &PathBuf) -> Self {
+        let recorder = NoStdTrainingRecorder::new();
+        if let Ok(file) = recorder.load::<MultiNetFile<B>>(model_path.into(), &device) {
+            let config = match CheckpointInfo::from_json(&file.info) {
+                Ok(info) => info.model,
+                Err(_) => {
+                    let encoding = Encoding::from_str(&file.info).expect("Failed to load model");
+                    config.with_encoding(encoding)
+                }
+            };
+            return Self::new_from(config, file.model);
+        }
+        let record = recorder
+            .load(model_path.into(), &device)
+            .expect("Failed to load model");
+        Self::new_from(config.with_encoding(Encoding::Standard), record)
+    }
+
+    pub fn device(&self) -> B::Device {
+        self.contact.device()
+    }
+
+    pub fn contact(&self) -> &TDModel<B> {
+        &self.contact
+    }
+
+    pub fn race(&self) -> &TDModel<B> {
+        &self.race
+    }
+
+    fn is_contact<G: State>(position: &G) -> bool {
+        matches!(position.phase(), GamePhase::Ongoing(Phase::Contact))
+    }
+
+    fn net<G: State>(&self, position: &G) -> &TDModel<B> {
+        if Self::is_contact(position) {
+            &self.contact
+        } else {
+            &self.race
+        }
+    }
+}
+
+impl<B: Backend> TDNetwork<B> for MultiNet<B> {
+    fn forward_pos<G: State>(&self, position: G) -> Tensor<B, 1> {
+        self.net(&position).forward_pos(position)
+    }
+
+    fn from_result(&self, result: GameResult) -> Tensor<B, 1> {
+        self.race.from_result(result)
+    }
+
+    const KIND: &'static str = "multi";
+
+    /// Both sub-nets share one configuration.
+    fn config(&self) -> ModelConfig {
+        self.contact.config()
+    }
+
+    fn save(self, path: PathBuf, info: CheckpointInfo) -> Result<(), RecorderError> {
+        let file = MultiNetFile {
+            model: self.into_record(),
+            info: info.to_json(),
+        };
+        NoStdTrainingRecorder::new().record(file, path)
+    }
+
+    fn load_checkpoint(
+        path: &PathBuf,
+        device: &B::Device,
+    ) -> Result<(Self, CheckpointInfo), String> {
+        let file = NoStdTrainingRecorder::new()
+            .load::<MultiNetFile<B>>(path.into(), device)
+            .map_err(|e| format!("Failed to load {}: {:?}", path.display(), e))?;
+        let info = CheckpointInfo::from_json(&file.info).map_err(|_| {
+            format!(
+                "{} has no model configuration, load it with init_with",
+                path.display()
+            )
+        })?;
+        info.check(<Self as TDNetwork<B>>::KIND)?;
+        let model = Self::new_from(info.model.clone(), file.model);
+        model.contact.check_config(&info.model)?;
+        model.race.check_config(&info.model)?;
+        Ok((model, info))
+    }
+}
+
+impl<B: Backend> BatchEvaluator for MultiNet<B> {
+    fn evaluate<G: State>(&self, positions: &[G]) -> Vec<Probabilities> {
+        let (contact, race): (Vec<usize>, Vec<usize>) =
+            (0..positions.len()).partition(|&i| Self::is_contact(&positions[i]));
+
+        let mut probabilities = vec![Probabilities::empty(); positions.len()];
+        for (indices, net) in [(contact, &self.contact), (race, &self.race)] {
+            if indices.is_empty() {
+                continue;
+            }
+            let batch: Vec<G> = indices.iter().map(|&i| positions[i]).collect();
+            for (i, probs) in indices.into_iter().zip(net.evaluate(&batch)) {
+                probabilities[i] = probs;
+            }
+        }
+        probabilities
+    }
+}
+
+impl<G: State + Send, B: Backend> Evaluator<FState<G>> for MultiNet<B> {
+    fn best_position(&self, pos: &FState<G>, dice: &Dice) -> FState<G> {
+        search::best_position(self, 1, pos, dice)
+    }
+}
+
+impl<G: State + Send, B: Backend> PositionEvaluator<FState<G>> for MultiNet<B> {
+    fn eval(&self, pos: &FState<G>) -> Probabilities {
+        self.evaluate(&[*pos])[0]
+    }
+
+    fn eval_batch(&self, positions: &[FState<G>]) -> Vec<Probabilities> {
+        self.evaluate(positions)
+    }
+
+    /// The same ranking as `best_position`, see `search::ranked_positions`.
+    fn ranked_positions(&self, pos: &FState<G>, dice: &Dice) -> Vec<(FState<G>, Probabilities)> {
+        search::ranked_positions(self, pos, dice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{device, DefaultBackend, TrainingBackend};
+    use crate::model::{ModelConfig, TDModel, TDNetwork};
+    use crate::multinet::MultiNet;
+    use crate::search::BatchEvaluator;
+    use crate::test_dir::TestDir;
+    use bkgm::{pos, Backgammon, State};
+    use burn::tensor::backend::AutodiffBackend;
+
+    fn contact() -> Backgammon {
+        Backgammon::new()
+    }
+
+    fn race() -> Backgammon {
+        Backgammon::from_position(pos!(x 6:5, 5:5, 4:5; o 19:5, 20:5, 21:5))
+    }
+
+    #[test]
+    fn positions_are_routed_by_phase() {
+        let model =
+            MultiNet::<DefaultBackend>::new(ModelConfig::new().with_neurons(8), &device(true));
+        let probs = model.evaluate(&[contact(), race()]);
+        assert_eq!(probs[0], model.contact().evaluate(&[contact()])[0]);
+        assert_eq!(probs[1], model.race().evaluate(&[race()])[0]);
+        assert_ne!(probs[1], model.contact().evaluate(&[race()])[0]);
+    }
+
+    /// For every layer of `net`, whether it got a gradient.
+    fn gradients(
+        net: &TDModel<TrainingBackend>,
+        grads: &<TrainingBackend as AutodiffBackend>::Gradients,
+    ) -> Vec<bool> {
+        net.layers()
+            .iter()
+            .map(|layer| layer.weight.grad(grads).is_some())
+            .collect()
+    }
+
+    #[test]
+    fn only_the_active_net_gets_gradients() {
+        let model =
+            MultiNet::<TrainingBackend>::new(ModelConfig::new().with_neurons(8), &device(true));
+        let grads = model.forward_pos(contact()).sum().backward();
+        assert_eq!(gradients(model.contact(), &grads), vec![true, true]);
+        assert_eq!(gradients(model.race(), &grads), vec![false, false]);
+
+        let grads = model.forward_pos(race()).sum().backward();
+        assert_eq!(gradients(model.contact(), &grads), vec![false, false]);
+        assert_eq!(gradients(model.race(), &grads), vec![true, true]);
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let device = device(true);
+        let config = ModelConfig::new().with_widths(vec![8, 4]);
+        let model = MultiNet::<DefaultBackend>::new(config, &device);
+        let positions = [contact(), race()];
+        let expected = model.evaluate(&positions);
+        let dir = TestDir::new("multinet");
+        let path = dir.join("model");
+        let info = model.checkpoint_info();
+        model.save(path.clone(), info).unwrap();
+
+        let loaded = MultiNet::<DefaultBackend>::load(&path, &device).unwrap();
+        assert_eq!(loaded.config().hidden_widths(), vec![8, 4]);
+        assert_eq!(loaded.evaluate(&positions), expected);
+    }
+}
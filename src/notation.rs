@@ -0,0 +1,384 @@
+use bkgm::{Dice, Position, State};
+
+const POSITION_ID_LENGTH: usize = 14;
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Parses either a GNU Backgammon Position ID or an XGID (with or without the `XGID=` prefix).
+///
+/// The returned position is always from the perspective of the player on roll.
+/// Dice are only returned if the XGID contains a roll.
+pub fn parse_position(input: &str) -> Result<(Position, Option<Dice>), String> {
+    let input = input.trim();
+    if let Some(xgid) = input.strip_prefix("XGID=").or(input.strip_prefix("xgid=")) {
+        parse_xgid(xgid)
+    } else if input.contains(':') {
+        parse_xgid(input)
+    } else {
+        parse_position_id(input).map(|position| (position, None))
+    }
+}
+
+pub fn parse_position_id(id: &str) -> Result<Position, String> {
+    if id.len() != POSITION_ID_LENGTH {
+        return Err(format!(
+            "Position ID must have {} characters, got {}",
+            POSITION_ID_LENGTH,
+            id.len()
+        ));
+    }
+    if !id.bytes().all(|c| BASE64.contains(&c)) {
+        return Err(format!("Position ID '{}' contains invalid characters", id));
+    }
+    Ok(Position::from_id(id.to_string()))
+}
+
+/// See https://www.extremegammon.com/extremegammon2.pdf for the format.
+///
+/// X is the player at the bottom, whose checkers are written in upper case.
+pub fn parse_xgid(xgid: &str) -> Result<(Position, Option<Dice>), String> {
+    let fields: Vec<&str> = xgid.split(':').collect();
+    if fields.len() < 5 {
+        return Err(format!("XGID '{}' has too few fields", xgid));
+    }
+
+    let board = fields[0].as_bytes();
+    if board.len() != 26 {
+        return Err(format!(
+            "XGID board must have 26 characters, got {}",
+            board.len()
+        ));
+    }
+    // Index 0 is o's bar, index 25 is x's bar, same as in the XGID.
+    let mut pips = [0_i8; 26];
+    for (i, c) in board.iter().enumerate() {
+        pips[i] = match c {
+            b'-' => 0,
+            b'A'..=b'P' => (c - b'A' + 1) as i8,
+            b'a'..=b'p' => -((c - b'a' + 1) as i8),
+            _ => return Err(format!("Invalid character '{}' in XGID board", *c as char)),
+        };
+    }
+    if pips[0] > 0 || pips[25] < 0 {
+        return Err("XGID has checkers of the wrong player on a bar".to_string());
+    }
+    let position = Position::try_from(pips).map_err(|e| e.to_string())?;
+
+    let position = match fields[3] {
+        "1" => position,
+        "-1" => position.flip(),
+        turn => return Err(format!("Invalid turn '{}' in XGID", turn)),
+    };
+
+    let dice = match fields[4] {
+        "00" | "D" | "B" | "R" => None,
+        roll => Some(parse_dice(roll)?),
+    };
+
+    Ok((position, dice))
+}
+
+/// Board as array, index 25 is x's bar, index 0 is o's bar with negative values.
+/// This is the layout `Position::try_from` expects.
+pub fn pips(position: &Position) -> [i8; 26] {
+    let mut pips = [0_i8; 26];
+    pips[0] = -(position.o_bar() as i8);
+    for (i, pip) in pips.iter_mut().enumerate().take(25).skip(1) {
+        *pip = position.pip(i);
+    }
+    pips[25] = position.x_bar() as i8;
+    pips
+}
+
+/// Finds the checker moves which turn `before` into `after` using `dice`.
+///
+/// `after` is from the perspective of the opponent, as returned by `possible_positions`.
+/// Each move is `(from, to)` from the perspective of the player moving, 25 is the bar and 0 is off.
+pub fn moves_between(
+    before: &Position,
+    after: &Position,
+    dice: &Dice,
+) -> Option<Vec<(usize, usize)>> {
+    let dice = match *dice {
+        Dice::Double(die) => vec![die; 4],
+        Dice::Regular(dice) => vec![dice.big, dice.small],
+    };
+    let mut moves = Vec::with_capacity(dice.len());
+    let found = search_moves(pips(before), &pips(&after.flip()), &dice, &mut moves);
+    found.then_some(moves)
+}
+
+fn search_moves(
+    board: [i8; 26],
+    target: &[i8; 26],
+    dice: &[usize],
+    moves: &mut Vec<(usize, usize)>,
+) -> bool {
+    if board == *target {
+        return true;
+    }
+    for (i, die) in dice.iter().enumerate() {
+        // Trying the same die value twice gives the same moves.
+        if dice[..i].contains(die) {
+            continue;
+        }
+        let remaining: Vec<usize> = dice[..i].iter().chain(&dice[i + 1..]).copied().collect();
+        for from in (1..=25).rev() {
+            if let Some((next, to)) = single_move(&board, from, *die) {
+                moves.push((from, to));
+                if search_moves(next, target, &remaining, moves) {
+                    return true;
+                }
+                moves.pop();
+            }
+        }
+    }
+    false
+}
+
+/// Moves a single checker of x from `from` by `die` pips, if that is legal.
+pub(crate) fn single_move(board: &[i8; 26], from: usize, die: usize) -> Option<([i8; 26], usize)> {
+    if board[from] <= 0 || (board[25] > 0 && from != 25) {
+        return None;
+    }
+    let mut next = *board;
+    next[from] -= 1;
+    if from > die {
+        let to = from - die;
+        match board[to] {
+            -1 => {
+                next[to] = 1;
+                next[0] -= 1;
+            }
+            n if n < -1 => return None,
+            _ => next[to] += 1,
+        }
+        Some((next, to))
+    } else {
+        let all_home = board[7..=25].iter().all(|&n| n <= 0);
+        let exact_or_highest = from == die || board[from + 1..=6].iter().all(|&n| n <= 0);
+        (all_home && exact_or_highest).then_some((next, 0))
+    }
+}
+
+/// Standard notation like "bar/22 13/8 6/off", moves are sorted from the highest point.
+pub fn format_moves(moves: &[(usize, usize)]) -> String {
+    let mut moves = moves.to_vec();
+    moves.sort_by(|a, b| b.cmp(a));
+    moves
+        .iter()
+        .map(|(from, to)| format!("{}/{}", format_point(*from), format_point(*to)))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn format_point(point: usize) -> String {
+    match point {
+        25 => "bar".to_string(),
+        0 => "off".to_string(),
+        point => point.to_string(),
+    }
+}
+
+/// Parses standard notation like "bar/22 13/8* 6/5(2)" or "24/18/13" into `(from, to)` moves.
+/// Hit markers are accepted but not required.
+pub fn parse_moves(input: &str) -> Result<Vec<(usize, usize)>, String> {
+    let mut moves = Vec::new();
+    for part in input.split_whitespace() {
+        let (part, repeat) = match part.split_once('(') {
+            Some((part, repeat)) => {
+                let repeat = repeat
+                    .strip_suffix(')')
+                    .and_then(|r| r.parse::<usize>().ok())
+                    .filter(|r| (1..=4).contains(r))
+                    .ok_or_else(|| format!("Invalid repetition in '{}'", input))?;
+                (part, repeat)
+            }
+            None => (part, 1),
+        };
+        let points = part
+            .split('/')
+            .map(parse_point)
+            .collect::<Result<Vec<usize>, String>>()?;
+        if points.len() < 2 {
+            return Err(format!("Invalid move '{}'", part));
+        }
+        for _ in 0..repeat {
+            for pair in points.windows(2) {
+                if pair[0] <= pair[1] {
+                    return Err(format!(
+                        "Checkers can't move from {} to {}",
+                        pair[0], pair[1]
+                    ));
+                }
+                moves.push((pair[0], pair[1]));
+            }
+        }
+    }
+    Ok(moves)
+}
+
+fn parse_point(point: &str) -> Result<usize, String> {
+    match point.trim_end_matches('*') {
+        "bar" | "b" => Ok(25),
+        "off" | "o" => Ok(0),
+        point => point
+            .parse::<usize>()
+            .ok()
+            .filter(|p| (1..=24).contains(p))
+            .ok_or_else(|| format!("Invalid point '{}'", point)),
+    }
+}
+
+/// Finds the legal move for `dice` which `input` in standard notation describes.
+pub fn find_move<G: State>(pos: &G, dice: &Dice, input: &str) -> Result<G, String> {
+    let mut board = pips(&pos.position());
+    for (from, to) in parse_moves(input)? {
+        if board[from] <= 0 {
+            return Err(format!("No checker on {}", format_point(from)));
+        }
+        board[from] -= 1;
+        if to > 0 {
+            match board[to] {
+                -1 => {
+                    board[to] = 1;
+                    board[0] -= 1;
+                }
+                n if n < -1 => return Err(format!("Point {} is blocked", to)),
+                _ => board[to] += 1,
+            }
+        }
+    }
+    pos.possible_positions(dice)
+        .into_iter()
+        .find(|p| pips(&p.position().flip()) == board)
+        .ok_or_else(|| format!("'{}' is not a legal move", input))
+}
+
+/// Accepts "52", "5 2", "5-2" and "5,2".
+pub fn parse_dice(input: &str) -> Result<Dice, String> {
+    let digits: Vec<usize> = input
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | ','))
+        .map(|c| c.to_digit(10).map(|d| d as usize))
+        .collect::<Option<Vec<usize>>>()
+        .ok_or_else(|| format!("Invalid dice '{}'", input))?;
+    match digits.as_slice() {
+        [die1, die2] if (1..=6).contains(die1) && (1..=6).contains(die2) => {
+            Ok(Dice::new(*die1, *die2))
+        }
+        _ => Err(format!("Invalid dice '{}'", input)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::notation::{
+        find_move, format_moves, moves_between, parse_dice, parse_moves, parse_position,
+        parse_position_id, parse_xgid,
+    };
+    use bkgm::{pos, Backgammon, Dice, Position, State};
+
+    #[test]
+    fn dice_formats() {
+        assert_eq!(parse_dice("52"), Ok(Dice::new(5, 2)));
+        assert_eq!(parse_dice("2 5"), Ok(Dice::new(5, 2)));
+        assert_eq!(parse_dice("3-3"), Ok(Dice::new(3, 3)));
+        assert!(parse_dice("7 1").is_err());
+        assert!(parse_dice("1").is_err());
+        assert!(parse_dice("ab").is_err());
+    }
+
+    #[test]
+    fn starting_position_id() {
+        let position = parse_position_id("4HPwATDgc/ABMA").unwrap();
+        assert_eq!(position, Position::from_id("4HPwATDgc/ABMA".to_string()));
+        assert!(parse_position_id("4HPwATDgc/AB").is_err());
+        assert!(parse_position_id("4HPwATDgc/AB!A").is_err());
+    }
+
+    #[test]
+    fn starting_xgid_matches_position_id() {
+        let (position, dice) =
+            parse_position("XGID=-b----E-C---eE---c-e----B-:0:0:1:52:0:0:0:0:10").unwrap();
+        let (from_id, _) = parse_position("4HPwATDgc/ABMA").unwrap();
+        assert_eq!(position, from_id);
+        assert_eq!(dice, Some(Dice::new(5, 2)));
+    }
+
+    #[test]
+    fn xgid_turn_flips_position() {
+        let (x_on_roll, dice) =
+            parse_xgid("-b----E-C---eE---c-e----B-:0:0:1:00:0:0:0:0:10").unwrap();
+        let (o_on_roll, _) = parse_xgid("-b----E-C---eE---c-e----B-:0:0:-1:00:0:0:0:0:10").unwrap();
+        assert_eq!(x_on_roll.flip(), o_on_roll);
+        assert_eq!(dice, None);
+    }
+
+    #[test]
+    fn xgid_invalid_board() {
+        assert!(parse_xgid("-b----E-C---eE---c-e----B:0:0:1:00:0:0:0:0:10").is_err());
+        assert!(parse_xgid("-b----E-C---eE---c-e----Bz:0:0:1:00:0:0:0:0:10").is_err());
+        assert!(parse_xgid("-b----E-C---eE---c-e----B-:0:0").is_err());
+    }
+
+    #[test]
+    fn moves_between_regular_roll() {
+        let before = Position::from_id("4HPwATDgc/ABMA".to_string());
+        let after = Backgammon::from_position(before)
+            .possible_positions(&Dice::new(3, 1))
+            .into_iter()
+            .map(|p| p.position())
+            .find(|p| p.flip().pip(5) == 2)
+            .unwrap();
+        let moves = moves_between(&before, &after, &Dice::new(3, 1)).unwrap();
+        assert_eq!(format_moves(&moves), "8/5 6/5");
+    }
+
+    #[test]
+    fn moves_between_bar_and_hit() {
+        let before = pos!(x 25:1, 10:1; o 20:1);
+        let after = pos!(x 20:1, 8:1; o 0:1).flip();
+        let moves = moves_between(&before, &after, &Dice::new(5, 2)).unwrap();
+        assert_eq!(format_moves(&moves), "bar/20 10/8");
+    }
+
+    #[test]
+    fn moves_between_bear_off() {
+        let before = pos!(x 5:1, 2:1; o 20:1);
+        let after = pos!(x; o 20:1).flip();
+        let moves = moves_between(&before, &after, &Dice::new(6, 2)).unwrap();
+        assert_eq!(format_moves(&moves), "5/off 2/off");
+    }
+
+    #[test]
+    fn moves_between_unrelated_positions() {
+        let before = pos!(x 6:2; o 19:2);
+        let after = pos!(x 1:2; o 19:2).flip();
+        assert_eq!(moves_between(&before, &after, &Dice::new(2, 1)), None);
+    }
+
+    #[test]
+    fn parse_moves_formats() {
+        assert_eq!(parse_moves("8/5 6/5"), Ok(vec![(8, 5), (6, 5)]));
+        assert_eq!(parse_moves("bar/22* 6/off"), Ok(vec![(25, 22), (6, 0)]));
+        assert_eq!(parse_moves("24/18/13"), Ok(vec![(24, 18), (18, 13)]));
+        assert_eq!(parse_moves("6/2(2)"), Ok(vec![(6, 2), (6, 2)]));
+        assert!(parse_moves("5/8").is_err());
+        assert!(parse_moves("25/20").is_err());
+        assert!(parse_moves("8").is_err());
+        assert!(parse_moves("8/5(5)").is_err());
+    }
+
+    #[test]
+    fn find_legal_and_illegal_moves() {
+        let start = Backgammon::from_position(Position::from_id("4HPwATDgc/ABMA".to_string()));
+        let dice = Dice::new(3, 1);
+        let moved = find_move(&start, &dice, "8/5 6/5").unwrap();
+        assert_eq!(moved.position().flip().pip(5), 2);
+        assert_eq!(find_move(&start, &dice, "6/5 8/5"), Ok(moved));
+        assert!(find_move(&start, &dice, "8/4").is_ok());
+        assert!(find_move(&start, &dice, "8/5").is_err());
+        assert!(find_move(&start, &dice, "13/9").is_err());
+        assert!(find_move(&start, &dice, "7/4 6/5").is_err());
+    }
+}
@@ -19,12 +19,13 @@ impl Serialize for Probabilities {
     where
         S: serde::Serializer,
     {
-        let mut s = serializer.serialize_struct("Probabilities", 5)?;
+        let mut s = serializer.serialize_struct("Probabilities", 6)?;
         s.serialize_field("win", &(self.win_n + self.win_g + self.win_b))?;
         s.serialize_field("win_g", &(self.win_g + self.win_b))?;
         s.serialize_field("win_b", &(self.win_b))?;
         s.serialize_field("lose_g", &(self.lose_g + self.lose_b))?;
         s.serialize_field("lose_b", &(self.lose_b))?;
+        s.serialize_field("equity", &self.equity())?;
         s.end()
     }
 }
@@ -443,4 +444,20 @@ mod probabilities_tests {
         let gv = probabilities.to_gnu();
         assert_eq!(probabilities, Probabilities::from(&gv));
     }
+
+    #[test]
+    fn serialize() {
+        let probabilities = Probabilities {
+            win_n: 0.25,
+            win_g: 0.125,
+            win_b: 0.125,
+            lose_n: 0.5,
+            lose_g: 0.0,
+            lose_b: 0.0,
+        };
+        assert_eq!(
+            serde_json::to_string(&probabilities).unwrap(),
+            r#"{"win":0.5,"win_g":0.25,"win_b":0.125,"lose_g":0.0,"lose_b":0.0,"equity":0.375}"#
+        );
+    }
 }
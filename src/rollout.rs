@@ -0,0 +1,74 @@
+use crate::dicegen::DiceGen;
+use crate::evaluator::Evaluator;
+use crate::probabilities::{Probabilities, ResultCounter};
+use bkgm::GameState::{GameOver, Ongoing};
+use bkgm::State;
+
+/// Plays `games` games from `pos` to the end, with `evaluator` choosing the moves of both sides.
+/// The result is from the perspective of the player on roll in `pos`.
+pub fn rollout<G: State, E: Evaluator<G>, D: DiceGen>(
+    evaluator: &E,
+    pos: &G,
+    games: usize,
+    dice_gen: &mut D,
+) -> Probabilities {
+    rollout_results(evaluator, pos, games, dice_gen).probabilities()
+}
+
+/// Same as `rollout`, but counts the results, so that rollouts in parts can be combined.
+pub fn rollout_results<G: State, E: Evaluator<G>, D: DiceGen>(
+    evaluator: &E,
+    pos: &G,
+    games: usize,
+    dice_gen: &mut D,
+) -> ResultCounter {
+    let mut counter = ResultCounter::default();
+    for _ in 0..games {
+        counter.add(single_rollout(evaluator, pos, dice_gen));
+    }
+    counter
+}
+
+fn single_rollout<G: State, E: Evaluator<G>, D: DiceGen>(
+    evaluator: &E,
+    pos: &G,
+    dice_gen: &mut D,
+) -> bkgm::GameResult {
+    let mut pos = *pos;
+    let mut our_turn = true;
+    loop {
+        match pos.game_state() {
+            Ongoing => {
+                pos = evaluator.best_position(&pos, &dice_gen.roll());
+                our_turn = !our_turn;
+            }
+            GameOver(result) => {
+                return if our_turn { result } else { result.reverse() };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dicegen::{DiceGenMock, FastrandDice};
+    use crate::evaluator::RandomEvaluator;
+    use crate::rollout::rollout;
+    use bkgm::{pos, Backgammon, Dice, State};
+
+    #[test]
+    fn last_checker_always_wins() {
+        let pos = Backgammon::from_position(pos!(x 1:1; o 24:1));
+        let probs = rollout(&RandomEvaluator, &pos, 100, &mut FastrandDice::with_seed(1));
+        assert_eq!(probs.win_n, 1.0);
+    }
+
+    #[test]
+    fn perspective_after_opponent_wins() {
+        let pos = Backgammon::from_position(pos!(x 20:1; o 24:1));
+        let mut dice = DiceGenMock::new(&[Dice::new(2, 1), Dice::new(2, 1)]);
+        let probs = rollout(&RandomEvaluator, &pos, 1, &mut dice);
+        dice.assert_all_dice_were_used();
+        assert_eq!(probs.lose_n, 1.0);
+    }
+}
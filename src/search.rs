@@ -0,0 +1,195 @@
+use crate::fstate::FState;
+use crate::probabilities::Probabilities;
+use bkgm::dice::ALL_21;
+use bkgm::GameState::{GameOver, Ongoing};
+use bkgm::{Dice, State};
+
+/// Networks that evaluate many positions at once, the n-ply move search is shared between them.
+pub trait BatchEvaluator {
+    /// Probabilities for every position, from the perspective of the player on roll.
+    fn evaluate<G: State>(&self, positions: &[G]) -> Vec<Probabilities>;
+}
+
+/// Best move for `dice`, searched `depth` plies deep.
+pub fn best_position<G: State + Send, E: BatchEvaluator>(
+    evaluator: &E,
+    depth: usize,
+    pos: &FState<G>,
+    dice: &Dice,
+) -> FState<G> {
+    nply(evaluator, depth, pos, dice).0
+}
+
+/// All legal moves for `dice`, best first, with the probabilities of the player who made them.
+/// Finished games are scored by their result. The evaluator judges the other moves from the
+/// board of the player whose `turn` is false. `best_position` plays the first move.
+pub fn ranked_positions<G: State + Send, E: BatchEvaluator>(
+    evaluator: &E,
+    pos: &FState<G>,
+    dice: &Dice,
+) -> Vec<(FState<G>, Probabilities)> {
+    let positions = pos.possible_positions(dice);
+    let ongoing: Vec<FState<G>> = positions
+        .iter()
+        .filter(|p| p.game_state() == Ongoing)
+        .map(|p| if pos.turn { *p } else { p.flip() })
+        .collect();
+    let mut evaluations = evaluator.evaluate(&ongoing).into_iter();
+    let mut ranked: Vec<(FState<G>, Probabilities)> = positions
+        .into_iter()
+        .map(|p| {
+            let probs = match p.game_state() {
+                // From the perspective of the player on roll after the move.
+                GameOver(result) => Probabilities::from_result(&result).flip(),
+                Ongoing if pos.turn => evaluations.next().unwrap().flip(),
+                Ongoing => evaluations.next().unwrap(),
+            };
+            (p, probs)
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.1.equity().partial_cmp(&a.1.equity()).unwrap());
+    ranked
+}
+
+/// Values are the equities of the player whose `turn` is false, at every ply.
+/// That player maximises them, the other one minimises them.
+fn finder<G: State + Send, E: BatchEvaluator>(
+    evaluator: &E,
+    pos: &FState<G>,
+    dice: &Dice,
+) -> (FState<G>, f32) {
+    let (best, probs) = ranked_positions(evaluator, pos, dice)[0];
+    (best, false_equity(pos, probs.equity()))
+}
+
+/// Same values as `finder`, each move is judged by the average over the rolls after it.
+fn nply<G: State + Send, E: BatchEvaluator>(
+    evaluator: &E,
+    depth: usize,
+    pos: &FState<G>,
+    dice: &Dice,
+) -> (FState<G>, f32) {
+    if depth == 1 {
+        return finder(evaluator, pos, dice);
+    }
+
+    let values = pos
+        .possible_positions(dice)
+        .into_iter()
+        .map(|p| match p.game_state() {
+            GameOver(result) => {
+                let probs = Probabilities::from_result(&result).flip();
+                (p, false_equity(pos, probs.equity()))
+            }
+            Ongoing => {
+                let mut nprobs = 0.0;
+                let mut total = 0.0;
+                for (dice, n) in ALL_21 {
+                    let (_, v) = nply(evaluator, depth - 1, &p, &dice);
+                    total += v * n;
+                    nprobs += n;
+                }
+                (p, total / nprobs)
+            }
+        });
+    select(pos.turn, values)
+}
+
+fn select<G>(minimise: bool, values: impl Iterator<Item = (G, f32)>) -> (G, f32) {
+    if minimise {
+        values
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap()
+    } else {
+        values
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap()
+    }
+}
+
+/// The `equity` of the player who moved from `pos` for the player whose `turn` is false.
+fn false_equity<G: State + Send>(pos: &FState<G>, equity: f32) -> f32 {
+    if pos.turn {
+        -equity
+    } else {
+        equity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fstate::FState;
+    use crate::probabilities::Probabilities;
+    use crate::search::{nply, BatchEvaluator};
+    use bkgm::dice::ALL_21;
+    use bkgm::{Dice, Hypergammon, State};
+
+    /// Every position has an equity of 0.5 for the player on roll.
+    struct Constant;
+
+    impl BatchEvaluator for Constant {
+        fn evaluate<G: State>(&self, positions: &[G]) -> Vec<Probabilities> {
+            vec![Probabilities::from(&[0.75, 0.0, 0.0, 0.0, 0.0]); positions.len()]
+        }
+    }
+
+    #[test]
+    fn rolls_are_weighted_by_their_probability() {
+        // Non-doubles come up twice as often, the average of a constant is the constant.
+        let start = FState::<Hypergammon>::new();
+        let (_, value) = nply(&Constant, 2, &start, &Dice::new(3, 1));
+        assert!((value - 0.5).abs() < 1e-6, "{}", value);
+    }
+
+    /// Winning chances which depend on where the checkers are, not only on the pips.
+    struct Shape;
+
+    impl BatchEvaluator for Shape {
+        fn evaluate<G: State>(&self, positions: &[G]) -> Vec<Probabilities> {
+            positions
+                .iter()
+                .map(|pos| {
+                    let shape: f32 = pos
+                        .board()
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &checkers)| (i + 1) as f32 * (checkers as f32).powi(2))
+                        .sum();
+                    let win = 0.5 + 0.4 * (shape * 0.1).sin();
+                    Probabilities::from(&[win, 0.0, 0.0, 0.0, 0.0])
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn both_sides_maximise_their_own_equity() {
+        // Equities of the player whose turn is false. The reply to each move is its best one,
+        // then the player whose turn is true picks the move with the worst average for it.
+        let start = FState::<Hypergammon>::new();
+        let dice = Dice::new(3, 1);
+        let (best, expected) = start
+            .possible_positions(&dice)
+            .into_iter()
+            .map(|p| {
+                let mut total = 0.0;
+                let mut nprobs = 0.0;
+                for (roll, n) in ALL_21 {
+                    let reply = p
+                        .possible_positions(&roll)
+                        .iter()
+                        .map(|q| Shape.evaluate(&[q.flip()])[0].equity())
+                        .fold(f32::MIN, f32::max);
+                    total += reply * n;
+                    nprobs += n;
+                }
+                (p, total / nprobs)
+            })
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap();
+
+        let (position, value) = nply(&Shape, 2, &start, &dice);
+        assert!((value - expected).abs() < 1e-5, "{} != {}", value, expected);
+        assert_eq!(position, best);
+    }
+}
@@ -0,0 +1,471 @@
+//! HTTP/JSON evaluation service.
+//!
+//! All endpoints take a JSON body with the position, either as GNU Backgammon Position ID in
+//! `"position"` or as 26 integers in `"board"` (index 0 is o's bar with negative values,
+//! index 25 is x's bar). Positions are from the perspective of the player on roll.
+//!
+//! - `POST /evaluate` returns `{"probabilities": {...}}` for the player on roll.
+//! - `POST /moves` needs `"dice": [d1, d2]` and returns `{"moves": [...]}`, best move first.
+//!   Each move has the resulting `"position"` (from the opponent's perspective), the `"play"` in
+//!   standard notation and the `"probabilities"` for the player who moved.
+//! - `POST /rollout` accepts `"games"` (default 1296) and returns `{"probabilities": {...}}`.
+//!
+//! Evaluations of concurrent requests are collected and run through the evaluator together, so
+//! a neural net does one forward pass for all of them. Rollouts run on their own threads and
+//! send their evaluations through the same batches, so they don't hold up other requests.
+
+use std::io::Read;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::dicegen::FastrandDice;
+use crate::evaluator::{Evaluator, PositionEvaluator};
+use crate::notation::{format_moves, moves_between, parse_dice, parse_position_id};
+use crate::probabilities::{Probabilities, ResultCounter};
+use crate::rollout::rollout_results;
+use bkgm::{Dice, Position, State};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const DEFAULT_ROLLOUT_GAMES: usize = 1296;
+const MAX_ROLLOUT_GAMES: usize = 100_000;
+
+#[derive(Clone, Copy)]
+pub struct ServerConfig {
+    /// Number of threads answering HTTP requests
+    pub threads: usize,
+    /// Maximum number of positions in one batch
+    pub max_batch: usize,
+    /// How long to wait for further requests before evaluating a batch
+    pub batch_window: Duration,
+    /// Number of threads playing rollouts, a rollout is split between all of them
+    pub rollout_threads: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            threads: 8,
+            max_batch: 1024,
+            batch_window: Duration::from_millis(2),
+            rollout_threads: 4,
+        }
+    }
+}
+
+struct Job<G> {
+    positions: Vec<G>,
+    reply: Sender<Vec<Probabilities>>,
+}
+
+/// Handle to the thread owning the evaluator and to the rollout threads.
+///
+/// It is an evaluator itself, every evaluation goes through a batch.
+pub struct Batcher<G> {
+    jobs: Sender<Job<G>>,
+    rollouts: Arc<rayon::ThreadPool>,
+}
+
+impl<G> Clone for Batcher<G> {
+    fn clone(&self) -> Self {
+        Self {
+            jobs: self.jobs.clone(),
+            rollouts: self.rollouts.clone(),
+        }
+    }
+}
+
+impl<G: State + Send + 'static> Batcher<G> {
+    pub fn spawn<E: PositionEvaluator<G> + Send + 'static>(
+        evaluator: E,
+        config: ServerConfig,
+    ) -> Self {
+        let (jobs, receiver) = channel();
+        thread::spawn(move || Self::process(evaluator, receiver, config));
+        let rollouts = rayon::ThreadPoolBuilder::new()
+            .num_threads(config.rollout_threads.max(1))
+            .build()
+            .expect("Failed to start rollout threads");
+        Self {
+            jobs,
+            rollouts: Arc::new(rollouts),
+        }
+    }
+
+    /// Splits the games between the rollout threads, the calling thread only waits.
+    pub fn rollout(&self, position: G, games: usize) -> Probabilities {
+        let threads = self.rollouts.current_num_threads().clamp(1, games.max(1));
+        self.rollouts
+            .install(|| {
+                vec![position; threads]
+                    .into_par_iter()
+                    .enumerate()
+                    .map(|(i, position)| {
+                        let games = games / threads + usize::from(i < games % threads);
+                        rollout_results(self, &position, games, &mut FastrandDice::new())
+                    })
+                    .reduce(ResultCounter::default, |a, b| a.combine(&b))
+            })
+            .probabilities()
+    }
+
+    fn process<E: PositionEvaluator<G>>(
+        evaluator: E,
+        receiver: Receiver<Job<G>>,
+        config: ServerConfig,
+    ) {
+        while let Ok(first) = receiver.recv() {
+            let deadline = Instant::now() + config.batch_window;
+            let mut evals: Vec<Job<G>> = Vec::new();
+            let mut num_positions = 0;
+            let mut next = Some(first);
+            while let Some(job) = next.take() {
+                num_positions += job.positions.len();
+                evals.push(job);
+                if num_positions >= config.max_batch {
+                    break;
+                }
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                next = match receiver.recv_timeout(timeout) {
+                    Ok(job) => Some(job),
+                    Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+                };
+            }
+
+            let all: Vec<G> = evals
+                .iter()
+                .flat_map(|job| job.positions.iter().copied())
+                .collect();
+            let mut results = evaluator.eval_batch(&all).into_iter();
+            for job in evals {
+                // The requester may have gone away, nothing to do then.
+                let _ = job
+                    .reply
+                    .send(results.by_ref().take(job.positions.len()).collect());
+            }
+        }
+    }
+}
+
+impl<G: State + Send + 'static> Evaluator<G> for Batcher<G> {
+    fn best_position(&self, pos: &G, dice: &Dice) -> G {
+        self.ranked_positions(pos, dice)[0].0
+    }
+}
+
+impl<G: State + Send + 'static> PositionEvaluator<G> for Batcher<G> {
+    fn eval(&self, pos: &G) -> Probabilities {
+        self.eval_batch(&[*pos])[0]
+    }
+
+    fn eval_batch(&self, positions: &[G]) -> Vec<Probabilities> {
+        if positions.is_empty() {
+            return Vec::new();
+        }
+        let (reply, result) = channel();
+        self.jobs
+            .send(Job {
+                positions: positions.to_vec(),
+                reply,
+            })
+            .expect("Evaluation thread stopped");
+        result.recv().expect("Evaluation thread stopped")
+    }
+}
+
+#[derive(Deserialize)]
+struct PositionRequest {
+    position: Option<String>,
+    board: Option<[i8; 26]>,
+    dice: Option<[usize; 2]>,
+    games: Option<usize>,
+}
+
+impl PositionRequest {
+    fn position(&self) -> Result<Position, String> {
+        match (&self.position, &self.board) {
+            (Some(id), None) => parse_position_id(id),
+            (None, Some(board)) => Position::try_from(*board).map_err(|e| e.to_string()),
+            _ => Err("Exactly one of 'position' and 'board' is needed".to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct EvalResponse {
+    probabilities: Probabilities,
+}
+
+#[derive(Serialize)]
+struct MoveResponse {
+    position: String,
+    play: String,
+    probabilities: Probabilities,
+}
+
+#[derive(Serialize)]
+struct MovesResponse {
+    moves: Vec<MoveResponse>,
+}
+
+/// Answers the JSON `body` of a request to `path`, errors come with their HTTP status code.
+pub fn handle<G: State + Send + 'static>(
+    batcher: &Batcher<G>,
+    path: &str,
+    body: &str,
+) -> Result<String, (u16, String)> {
+    let request: PositionRequest =
+        serde_json::from_str(body).map_err(|e| (400, format!("Invalid JSON: {}", e)))?;
+    let position = request.position().map_err(|e| (400, e))?;
+    let state = G::from_position(position);
+
+    let response = match path {
+        "/evaluate" => serde_json::to_string(&EvalResponse {
+            probabilities: batcher.eval(&state),
+        }),
+        "/moves" => {
+            let [die1, die2] = request
+                .dice
+                .ok_or((400, "'dice' is needed for moves".to_string()))?;
+            let dice = parse_dice(&format!("{}{}", die1, die2)).map_err(|e| (400, e))?;
+            // Finished games are scored by their result, only the others go to the evaluator.
+            let moves = batcher
+                .ranked_positions(&state, &dice)
+                .into_iter()
+                .map(|(p, probabilities)| {
+                    let play = moves_between(&position, &p.position(), &dice)
+                        .map(|moves| format_moves(&moves))
+                        .unwrap_or_default();
+                    MoveResponse {
+                        position: p.position().position_id(),
+                        play,
+                        probabilities,
+                    }
+                })
+                .collect();
+            serde_json::to_string(&MovesResponse { moves })
+        }
+        "/rollout" => {
+            let games = request.games.unwrap_or(DEFAULT_ROLLOUT_GAMES);
+            if games == 0 || games > MAX_ROLLOUT_GAMES {
+                return Err((400, format!("'games' must be in 1..={}", MAX_ROLLOUT_GAMES)));
+            }
+            serde_json::to_string(&EvalResponse {
+                probabilities: batcher.rollout(state, games),
+            })
+        }
+        _ => return Err((404, format!("Unknown endpoint '{}'", path))),
+    };
+    response.map_err(|e| (500, e.to_string()))
+}
+
+/// Serves HTTP requests on `address` until the process is stopped.
+pub fn serve<G: State + Send + 'static, E: PositionEvaluator<G> + Send + 'static>(
+    evaluator: E,
+    address: &str,
+    config: ServerConfig,
+) {
+    let server = Arc::new(Server::http(address).expect("Failed to start HTTP server"));
+    let batcher = Batcher::spawn(evaluator, config);
+    let workers: Vec<_> = (0..config.threads)
+        .map(|_| {
+            let server = server.clone();
+            let batcher = batcher.clone();
+            thread::spawn(move || {
+                while let Ok(request) = server.recv() {
+                    respond(&batcher, request);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+}
+
+fn respond<G: State + Send + 'static>(batcher: &Batcher<G>, mut request: Request) {
+    let mut body = String::new();
+    let result = if *request.method() != Method::Post {
+        Err((405, "Only POST is supported".to_string()))
+    } else if request.as_reader().read_to_string(&mut body).is_err() {
+        Err((400, "Body is not valid UTF-8".to_string()))
+    } else {
+        let path = request.url().split('?').next().unwrap_or("").to_string();
+        handle(batcher, &path, &body)
+    };
+    let (status, body) = match result {
+        Ok(body) => (200, body),
+        Err((status, message)) => (status, serde_json::json!({ "error": message }).to_string()),
+    };
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header);
+    // The client may have disconnected already.
+    let _ = request.respond(response);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::evaluator::{Evaluator, PositionEvaluator};
+    use crate::probabilities::Probabilities;
+    use crate::server::{handle, Batcher, ServerConfig};
+    use bkgm::{pos, Backgammon, Dice, State};
+
+    /// Counts calls of `eval_batch`, always claims 60% winning chances.
+    #[derive(Clone)]
+    struct CountingEvaluator {
+        batches: Arc<AtomicUsize>,
+    }
+
+    impl Evaluator<Backgammon> for CountingEvaluator {
+        fn best_position(&self, pos: &Backgammon, dice: &Dice) -> Backgammon {
+            pos.possible_positions(dice)[0]
+        }
+    }
+
+    impl PositionEvaluator<Backgammon> for CountingEvaluator {
+        fn eval(&self, _pos: &Backgammon) -> Probabilities {
+            Probabilities {
+                win_n: 0.6,
+                win_g: 0.0,
+                win_b: 0.0,
+                lose_n: 0.4,
+                lose_g: 0.0,
+                lose_b: 0.0,
+            }
+        }
+
+        fn eval_batch(&self, positions: &[Backgammon]) -> Vec<Probabilities> {
+            self.batches.fetch_add(1, Ordering::SeqCst);
+            positions.iter().map(|p| self.eval(p)).collect()
+        }
+    }
+
+    fn batcher(window: Duration) -> (Batcher<Backgammon>, Arc<AtomicUsize>) {
+        let batches = Arc::new(AtomicUsize::new(0));
+        let evaluator = CountingEvaluator {
+            batches: batches.clone(),
+        };
+        let config = ServerConfig {
+            batch_window: window,
+            ..ServerConfig::default()
+        };
+        (Batcher::spawn(evaluator, config), batches)
+    }
+
+    #[test]
+    fn evaluate_position_id_and_board() {
+        let (batcher, _) = batcher(Duration::ZERO);
+        let by_id = handle(&batcher, "/evaluate", r#"{"position": "4HPwATDgc/ABMA"}"#).unwrap();
+        let board = r#"{"board": [0,-2,0,0,0,0,5,0,3,0,0,0,-5,5,0,0,0,-3,0,-5,0,0,0,0,2,0]}"#;
+        let by_board = handle(&batcher, "/evaluate", board).unwrap();
+        assert_eq!(by_id, by_board);
+        assert!(by_id.starts_with(r#"{"probabilities":{"win":0.6"#));
+    }
+
+    #[test]
+    fn moves_are_ranked_with_plays() {
+        let (batcher, _) = batcher(Duration::ZERO);
+        let body = r#"{"position": "4HPwATDgc/ABMA", "dice": [3, 1]}"#;
+        let response: serde_json::Value =
+            serde_json::from_str(&handle(&batcher, "/moves", body).unwrap()).unwrap();
+        let moves = response["moves"].as_array().unwrap();
+        assert!(moves.len() > 1);
+        assert!(moves.iter().any(|m| m["play"] == "8/5 6/5"));
+        // From the perspective of the player who moved
+        assert_eq!(
+            moves[0]["probabilities"]["win"].as_f64().unwrap() as f32,
+            0.4
+        );
+    }
+
+    #[test]
+    fn finished_games_are_not_evaluated() {
+        let (batcher, batches) = batcher(Duration::ZERO);
+        let id = pos!(x 1:1; o 24:1).position_id();
+        let body = format!(r#"{{"position": "{}", "dice": [2, 1]}}"#, id);
+        let response: serde_json::Value =
+            serde_json::from_str(&handle(&batcher, "/moves", &body).unwrap()).unwrap();
+        assert_eq!(response["moves"][0]["probabilities"]["win"], 1.0);
+        assert_eq!(batches.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn rollouts_do_not_block_evaluations() {
+        let (batcher, _) = batcher(Duration::ZERO);
+        let won = pos!(x 1:1; o 24:1);
+        let body = format!(r#"{{"position": "{}", "games": 10}}"#, won.position_id());
+        let response = handle(&batcher, "/rollout", &body).unwrap();
+        assert!(response.starts_with(r#"{"probabilities":{"win":1.0"#));
+
+        // Holds every rollout thread, so the rollout can't finish before the gate opens.
+        let threads = batcher.rollouts.current_num_threads();
+        let barrier = Arc::new(Barrier::new(threads + 1));
+        for _ in 0..threads {
+            let barrier = barrier.clone();
+            batcher.rollouts.spawn(move || {
+                barrier.wait();
+            });
+        }
+        let rollout = {
+            let batcher = batcher.clone();
+            thread::spawn(move || batcher.rollout(Backgammon::from_position(won), 10))
+        };
+
+        let (sender, evaluation) = channel();
+        {
+            let batcher = batcher.clone();
+            thread::spawn(move || sender.send(batcher.eval(&Backgammon::new())));
+        }
+        let probs = evaluation
+            .recv_timeout(Duration::from_secs(10))
+            .expect("The evaluation waited for the rollout");
+        assert_eq!(probs.win_n, 0.6);
+        assert!(!rollout.is_finished());
+
+        barrier.wait();
+        assert_eq!(rollout.join().unwrap().win_n, 1.0);
+    }
+
+    #[test]
+    fn errors() {
+        let (batcher, _) = batcher(Duration::ZERO);
+        assert_eq!(handle(&batcher, "/evaluate", "{").unwrap_err().0, 400);
+        assert_eq!(handle(&batcher, "/evaluate", "{}").unwrap_err().0, 400);
+        let id = r#"{"position": "4HPwATDgc/ABMA"}"#;
+        assert_eq!(handle(&batcher, "/moves", id).unwrap_err().0, 400);
+        assert_eq!(handle(&batcher, "/unknown", id).unwrap_err().0, 404);
+        let rollout = r#"{"position": "4HPwATDgc/ABMA", "games": 0}"#;
+        assert_eq!(handle(&batcher, "/rollout", rollout).unwrap_err().0, 400);
+    }
+
+    #[test]
+    fn concurrent_requests_share_a_batch() {
+        let (batcher, batches) = batcher(Duration::from_millis(500));
+        let barrier = Arc::new(Barrier::new(8));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let batcher = batcher.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    batcher.eval_batch(&[Backgammon::new()])
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().len(), 1);
+        }
+        assert!(batches.load(Ordering::SeqCst) < 8);
+    }
+}
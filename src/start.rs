@@ -0,0 +1,270 @@
+//! Where training games start, see `TDConfig::start`.
+
+use crate::fstate::FState;
+use crate::notation::{parse_position, pips};
+use bkgm::{Dice, GameState::Ongoing, Position, State};
+use burn::config::Config;
+use std::str::FromStr;
+
+#[derive(Config, Debug, PartialEq)]
+pub enum StartPositions {
+    Opening,
+    /// Positions from a file, one GNU Backgammon Position ID or XGID per line, from the
+    /// perspective of the player on roll. Empty lines and lines starting with `#` are skipped.
+    /// Positions with more checkers than the game has, or finished ones, are errors.
+    File {
+        path: String,
+    },
+    /// The position after up to `max_moves` random moves from the opening.
+    Random {
+        max_moves: usize,
+    },
+    /// Bearoffs first, then back to the opening. In the first half of `episodes` games the
+    /// bearoffs grow from one checker per side to all of them, in the second half more and
+    /// more games start from the opening, afterwards all of them do.
+    Curriculum {
+        episodes: usize,
+    },
+}
+
+impl StartPositions {
+    /// Reads the position file, if there is one.
+    pub fn load<G: State + Send>(&self) -> Result<Starts<G>, String> {
+        let positions = match self {
+            StartPositions::File { path } => read_positions(path)?,
+            _ => Vec::new(),
+        };
+        Ok(Starts {
+            config: self.clone(),
+            positions,
+        })
+    }
+}
+
+impl FromStr for StartPositions {
+    type Err = String;
+
+    /// `opening`, `file=path`, `random=max_moves` or `curriculum=episodes`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s.split_once('=').unwrap_or((s, ""));
+        let number = || {
+            value
+                .parse()
+                .map_err(|_| format!("Invalid number '{}' in '{}'", value, s))
+        };
+        match name.to_lowercase().as_str() {
+            "opening" => Ok(StartPositions::Opening),
+            "file" if !value.is_empty() => Ok(StartPositions::File {
+                path: value.to_string(),
+            }),
+            "random" => Ok(StartPositions::Random {
+                max_moves: number()?,
+            }),
+            "curriculum" => Ok(StartPositions::Curriculum {
+                episodes: number()?,
+            }),
+            _ => Err(format!(
+                "Unknown start '{}', expected opening, file=path, random=max_moves or curriculum=episodes",
+                s
+            )),
+        }
+    }
+}
+
+fn read_positions<G: State + Send>(path: &str) -> Result<Vec<FState<G>>, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let mut positions = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |e: String| format!("{}, line {}: {}", path, i + 1, e);
+        let (position, _) = parse_position(line).map_err(error)?;
+        check_checkers::<G>(&position).map_err(error)?;
+        let state = FState::from_position(position);
+        if state.game_state() != Ongoing {
+            return Err(error("The game is already over".to_string()));
+        }
+        positions.push(state);
+    }
+    if positions.is_empty() {
+        return Err(format!("{} has no positions", path));
+    }
+    Ok(positions)
+}
+
+/// Position IDs and XGIDs don't say which game they are from, but neither side can have more
+/// checkers on the board than the game has.
+fn check_checkers<G: State>(position: &Position) -> Result<(), String> {
+    let pips = pips(position);
+    let x: i32 = pips
+        .iter()
+        .filter(|&&pip| pip > 0)
+        .map(|&pip| pip as i32)
+        .sum();
+    let o: i32 = pips
+        .iter()
+        .filter(|&&pip| pip < 0)
+        .map(|&pip| -pip as i32)
+        .sum();
+    if x.max(o) > G::NUM_CHECKERS as i32 {
+        return Err(format!(
+            "{} and {} checkers on the board, the game has {} per side",
+            x,
+            o,
+            G::NUM_CHECKERS
+        ));
+    }
+    Ok(())
+}
+
+/// `StartPositions` ready to be sampled.
+pub struct Starts<G: State> {
+    config: StartPositions,
+    positions: Vec<FState<G>>,
+}
+
+impl<G: State + Send> Starts<G> {
+    /// The start of game `episode`.
+    pub fn pick(&self, episode: usize, rng: &mut fastrand::Rng) -> FState<G> {
+        match &self.config {
+            StartPositions::Opening => FState::new(),
+            StartPositions::File { .. } => self.positions[rng.usize(..self.positions.len())],
+            StartPositions::Random { max_moves } => random_position(rng.usize(..=*max_moves), rng),
+            StartPositions::Curriculum { episodes } => {
+                let progress = episode as f64 / (*episodes).max(1) as f64;
+                if progress >= 1.0 || rng.f64() < 2.0 * progress - 1.0 {
+                    return FState::new();
+                }
+                let share = (2.0 * progress).min(1.0);
+                let checkers = ((share * G::NUM_CHECKERS as f64).ceil() as u8).max(1);
+                bearoff(checkers, rng)
+            }
+        }
+    }
+}
+
+/// Plays random moves from the opening, but stops before the game ends.
+fn random_position<G: State + Send>(moves: usize, rng: &mut fastrand::Rng) -> FState<G> {
+    let mut state = FState::<G>::new();
+    for _ in 0..moves {
+        let dice = Dice::new(rng.usize(1..=6), rng.usize(1..=6));
+        let positions = state.possible_positions(&dice);
+        let next = positions[rng.usize(..positions.len())];
+        if next.game_state() != Ongoing {
+            break;
+        }
+        state = next;
+    }
+    state
+}
+
+/// Both sides have between one and `max_checkers` checkers left, spread over their home boards.
+fn bearoff<G: State + Send>(max_checkers: u8, rng: &mut fastrand::Rng) -> FState<G> {
+    let mut pips = [0_i8; 26];
+    for _ in 0..rng.u8(1..=max_checkers) {
+        pips[rng.usize(1..=6)] += 1;
+    }
+    for _ in 0..rng.u8(1..=max_checkers) {
+        pips[25 - rng.usize(1..=6)] -= 1;
+    }
+    FState::from_position(Position::try_from(pips).expect("Bearoff positions are valid"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fstate::FState;
+    use crate::start::StartPositions;
+    use crate::test_dir::TestDir;
+    use bkgm::{Backgammon, GameState::Ongoing, Hypergammon, Position, State};
+    use std::str::FromStr;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            StartPositions::from_str("opening"),
+            Ok(StartPositions::Opening)
+        );
+        assert_eq!(
+            StartPositions::from_str("file=starts.txt"),
+            Ok(StartPositions::File {
+                path: "starts.txt".to_string()
+            })
+        );
+        assert_eq!(
+            StartPositions::from_str("curriculum=100000"),
+            Ok(StartPositions::Curriculum { episodes: 100_000 })
+        );
+        assert!(StartPositions::from_str("random").is_err());
+        assert!(StartPositions::from_str("file").is_err());
+    }
+
+    #[test]
+    fn curriculum_starts_with_bearoffs() {
+        let starts = StartPositions::Curriculum { episodes: 100 }
+            .load::<Hypergammon>()
+            .unwrap();
+        let mut rng = fastrand::Rng::with_seed(3);
+        for _ in 0..20 {
+            let state = starts.pick(0, &mut rng);
+            assert_eq!(state.game_state(), Ongoing);
+            let checkers: i8 = state.board().iter().map(|pip| pip.abs()).sum();
+            assert_eq!(checkers, 2);
+        }
+        assert_eq!(starts.pick(100, &mut rng), FState::new());
+    }
+
+    #[test]
+    fn random_positions_are_ongoing() {
+        let starts = StartPositions::Random { max_moves: 30 }
+            .load::<Hypergammon>()
+            .unwrap();
+        let mut rng = fastrand::Rng::with_seed(3);
+        for episode in 0..20 {
+            assert_eq!(starts.pick(episode, &mut rng).game_state(), Ongoing);
+        }
+    }
+
+    #[test]
+    fn position_file() {
+        let dir = TestDir::new("starts");
+        let path = dir.join("starts.txt");
+        let text = [
+            "# the opening, twice",
+            "",
+            "4HPwATDgc/ABMA",
+            "XGID=-b----E-C---eE---c-e----B-:0:0:1:00:0:0:0:0:10",
+        ];
+        std::fs::write(&path, text.join("\n")).unwrap();
+        let config = StartPositions::File {
+            path: path.display().to_string(),
+        };
+        let starts = config.load::<Backgammon>().unwrap();
+        assert_eq!(starts.positions.len(), 2);
+
+        // Backgammon positions have too many checkers for hypergammon.
+        let error = config.load::<Hypergammon>().err().unwrap();
+        assert!(
+            error.ends_with("line 3: 15 and 15 checkers on the board, the game has 3 per side"),
+            "{}",
+            error
+        );
+
+        // o has borne off all its checkers.
+        let mut pips = [0_i8; 26];
+        pips[1] = 1;
+        let finished = Position::try_from(pips).unwrap().position_id();
+        std::fs::write(&path, finished).unwrap();
+        let error = config.load::<Backgammon>().err().unwrap();
+        assert!(
+            error.ends_with("line 1: The game is already over"),
+            "{}",
+            error
+        );
+
+        std::fs::write(&path, "nonsense\n").unwrap();
+        assert!(config.load::<Backgammon>().is_err());
+    }
+}
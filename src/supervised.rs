@@ -0,0 +1,345 @@
+//! Supervised training of a `TDModel` on labelled positions, for example rollouts or the
+//! hypergammon database distilled into a net. The samples come from `dataset` files.
+
+use crate::dataset::{DatasetReader, Sample};
+use crate::metrics::{MetricsFormat, MetricsWriter};
+use crate::model::{TDModel, TDNetwork};
+use bkgm::Position;
+use burn::{
+    config::Config,
+    module::{AutodiffModule, Module},
+    nn::loss::{MseLoss, Reduction},
+    optim::{AdamConfig, GradientsParams, Optimizer},
+    tensor::{
+        backend::{AutodiffBackend, Backend},
+        Data, Shape, Tensor,
+    },
+};
+use serde::Serialize;
+use std::path::PathBuf;
+
+#[derive(Config)]
+pub struct SupervisedConfig {
+    /// Of the Adam optimiser.
+    #[config(default = 1e-3)]
+    pub learning_rate: f64,
+    #[config(default = 128)]
+    pub batch_size: usize,
+    #[config(default = 100)]
+    pub max_epochs: usize,
+    /// Share of the samples held out to decide when to stop, chosen by `seed`.
+    #[config(default = 0.1)]
+    pub validation: f64,
+    /// Epochs without a lower validation loss after which training stops, 0 trains for all
+    /// `max_epochs`.
+    #[config(default = 5)]
+    pub patience: usize,
+    /// Samples which are shuffled together. The files are streamed, never read at once.
+    #[config(default = 65_536)]
+    pub shuffle_buffer: usize,
+    #[config(default = 0)]
+    pub seed: u64,
+    #[config(default = "MetricsFormat::Csv")]
+    pub metrics_format: MetricsFormat,
+}
+
+/// One line of `supervised.csv`, the losses are mean squared errors of the outputs.
+#[derive(Serialize, Debug)]
+pub struct EpochRow {
+    pub epoch: usize,
+    pub train_loss: f32,
+    pub validation_loss: f32,
+    pub best: bool,
+}
+
+pub struct SupervisedTrainer<B: AutodiffBackend> {
+    device: B::Device,
+    config: SupervisedConfig,
+}
+
+/// Mean squared error of `model`'s outputs for `samples`.
+fn loss<B: Backend>(model: &TDModel<B>, samples: &[Sample]) -> Tensor<B, 1> {
+    let device = model.device();
+    let positions: Vec<Position> = samples.iter().map(|sample| sample.position).collect();
+    let targets: Vec<f32> = samples
+        .iter()
+        .flat_map(|sample| model.target(&sample.probabilities))
+        .collect();
+    let targets = Data::new(targets, Shape::new([samples.len(), model.num_outputs()]));
+    let targets = Tensor::from_data(targets.convert(), &device);
+    let outputs = model.forward(model.position_tensor(&device, &positions));
+    MseLoss::new().forward(outputs, targets, Reduction::Mean)
+}
+
+fn scalar<B: Backend>(tensor: Tensor<B, 1>) -> f32 {
+    let data: Data<f32, 1> = tensor.into_data().convert();
+    data.value[0]
+}
+
+/// Running mean of batch losses, weighted by the batch sizes.
+#[derive(Default)]
+struct MeanLoss {
+    sum: f64,
+    samples: usize,
+}
+
+impl MeanLoss {
+    fn add(&mut self, loss: f32, samples: usize) {
+        self.sum += loss as f64 * samples as f64;
+        self.samples += samples;
+    }
+
+    fn mean(&self) -> f32 {
+        (self.sum / self.samples.max(1) as f64) as f32
+    }
+}
+
+impl<B: AutodiffBackend> SupervisedTrainer<B> {
+    pub fn new(device: B::Device, config: SupervisedConfig) -> Self {
+        Self { device, config }
+    }
+
+    /// Whether the `index`th sample of the files is held out, the same ones in every epoch.
+    fn is_validation(&self, index: usize) -> bool {
+        let seed = self.config.seed ^ (index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        fastrand::Rng::with_seed(seed).f64() < self.config.validation
+    }
+
+    /// The samples of all files in order, each with its index.
+    fn samples<'a>(
+        &self,
+        paths: &'a [PathBuf],
+    ) -> impl Iterator<Item = (usize, Result<Sample, String>)> + 'a {
+        paths
+            .iter()
+            .flat_map(|path| {
+                let samples: Box<dyn Iterator<Item = Result<Sample, String>> + 'a> =
+                    match DatasetReader::open(path) {
+                        Ok(reader) => Box::new(
+                            reader
+                                .map(move |s| s.map_err(|e| format!("{}: {}", path.display(), e))),
+                        ),
+                        Err(e) => Box::new(std::iter::once(Err(e))),
+                    };
+                samples
+            })
+            .enumerate()
+    }
+
+    /// Shuffles the buffered samples, trains on them in mini-batches and empties the buffer.
+    fn train_buffer(
+        &self,
+        model: TDModel<B>,
+        optim: &mut impl Optimizer<TDModel<B>, B>,
+        buffer: &mut Vec<Sample>,
+        rng: &mut fastrand::Rng,
+        train_loss: &mut MeanLoss,
+    ) -> TDModel<B> {
+        let mut model = model;
+        rng.shuffle(buffer);
+        for batch in buffer.chunks(self.config.batch_size.max(1)) {
+            let loss = loss(&model, batch);
+            train_loss.add(scalar(loss.clone()), batch.len());
+            let grads = GradientsParams::from_grads(loss.backward(), &model);
+            model = optim.step(self.config.learning_rate, model, grads);
+        }
+        buffer.clear();
+        model
+    }
+
+    fn validation_loss(&self, model: &TDModel<B>, paths: &[PathBuf]) -> Result<MeanLoss, String> {
+        let model = model.valid();
+        let mut validation_loss = MeanLoss::default();
+        let mut batch = Vec::with_capacity(self.config.batch_size);
+        for (index, sample) in self.samples(paths) {
+            if !self.is_validation(index) {
+                continue;
+            }
+            batch.push(sample?);
+            if batch.len() >= self.config.batch_size.max(1) {
+                validation_loss.add(scalar(loss(&model, &batch)), batch.len());
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            validation_loss.add(scalar(loss(&model, &batch)), batch.len());
+        }
+        Ok(validation_loss)
+    }
+
+    /// Trains on the samples of `paths` until the validation loss stops improving and returns
+    /// the model with the lowest one. Without validation samples the training loss decides.
+    /// With a `dir`, the losses go to `supervised.csv` or `.jsonl` and the best model to `best`.
+    pub fn train(
+        &self,
+        model: TDModel<B>,
+        paths: &[PathBuf],
+        dir: Option<PathBuf>,
+    ) -> Result<TDModel<B>, String> {
+        let mut model = model.fork(&self.device);
+        let mut optim = AdamConfig::new().init();
+        let mut writer = match &dir {
+            Some(dir) => Some(MetricsWriter::open(
+                dir,
+                "supervised",
+                self.config.metrics_format,
+            )?),
+            None => None,
+        };
+        let mut best: Option<(f32, TDModel<B>)> = None;
+        let mut since_best = 0;
+
+        for epoch in 1..=self.config.max_epochs {
+            let mut rng = fastrand::Rng::with_seed(self.config.seed ^ epoch as u64);
+            let mut buffer = Vec::with_capacity(self.config.shuffle_buffer);
+            let mut train_loss = MeanLoss::default();
+            for (index, sample) in self.samples(paths) {
+                if self.is_validation(index) {
+                    continue;
+                }
+                buffer.push(sample?);
+                if buffer.len() >= self.config.shuffle_buffer.max(1) {
+                    model = self.train_buffer(
+                        model,
+                        &mut optim,
+                        &mut buffer,
+                        &mut rng,
+                        &mut train_loss,
+                    );
+                }
+            }
+            model = self.train_buffer(model, &mut optim, &mut buffer, &mut rng, &mut train_loss);
+            if train_loss.samples == 0 {
+                return Err("There are no training samples".to_string());
+            }
+
+            let validation_loss = self.validation_loss(&model, paths)?;
+            let loss = if validation_loss.samples > 0 {
+                validation_loss.mean()
+            } else {
+                train_loss.mean()
+            };
+            let is_best = match &best {
+                Some((best, _)) => loss < *best,
+                None => true,
+            };
+            if is_best {
+                best = Some((loss, model.clone()));
+                since_best = 0;
+                if let Some(dir) = &dir {
+                    let info = model.checkpoint_info();
+                    model
+                        .clone()
+                        .save(dir.join("best"), info)
+                        .map_err(|e| format!("Failed to save model: {:?}", e))?;
+                }
+            } else {
+                since_best += 1;
+            }
+
+            let row = EpochRow {
+                epoch,
+                train_loss: train_loss.mean(),
+                validation_loss: validation_loss.mean(),
+                best: is_best,
+            };
+            println!(
+                "epoch {}: train loss {:.6}, validation loss {:.6}{}",
+                row.epoch,
+                row.train_loss,
+                row.validation_loss,
+                if is_best { ", best" } else { "" }
+            );
+            if let Some(writer) = &mut writer {
+                writer.write(&row)?;
+            }
+            if self.config.patience > 0 && since_best >= self.config.patience {
+                break;
+            }
+        }
+        Ok(best.map(|(_, model)| model).unwrap_or(model))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{device, TrainingBackend};
+    use crate::dataset::{DatasetWriter, Sample};
+    use crate::model::{ModelConfig, TDModel};
+    use crate::probabilities::Probabilities;
+    use crate::supervised::{loss, scalar, SupervisedConfig, SupervisedTrainer};
+    use crate::test_dir::TestDir;
+    use bkgm::{pos, Position};
+
+    #[test]
+    fn learns_the_labels() {
+        let dir = TestDir::new("supervised");
+        let samples = [
+            Sample {
+                position: pos!(x 1:1; o 24:1),
+                probabilities: Probabilities::from(&[1.0, 0.0, 0.0, 0.0, 0.0]),
+            },
+            Sample {
+                position: Position::from_id("4HPwATDgc/ABMA".to_string()),
+                probabilities: Probabilities::from(&[0.5, 0.125, 0.0, 0.125, 0.0]),
+            },
+        ];
+        let path = dir.join("samples.bin");
+        let mut writer = DatasetWriter::create(&path).unwrap();
+        for _ in 0..50 {
+            for sample in &samples {
+                writer.write(sample).unwrap();
+            }
+        }
+        writer.flush().unwrap();
+        drop(writer);
+
+        let model =
+            TDModel::<TrainingBackend>::new(ModelConfig::new().with_neurons(8), &device(true));
+        let before = scalar(loss(&model, &samples));
+        let config = SupervisedConfig::new()
+            .with_learning_rate(0.01)
+            .with_batch_size(10)
+            .with_max_epochs(20)
+            .with_shuffle_buffer(30);
+        let trainer = SupervisedTrainer::new(device(true), config);
+        let model = trainer
+            .train(model, &[path], Some(dir.path().to_path_buf()))
+            .unwrap();
+        assert!(scalar(loss(&model, &samples)) < before / 2.0);
+
+        let losses = std::fs::read_to_string(dir.join("supervised.csv")).unwrap();
+        assert!(losses.starts_with("epoch,train_loss,validation_loss,best"));
+    }
+
+    #[test]
+    fn no_patience_trains_all_epochs() {
+        let dir = TestDir::new("supervised_patience");
+        let path = dir.join("samples.bin");
+        let mut writer = DatasetWriter::create(&path).unwrap();
+        writer
+            .write(&Sample {
+                position: pos!(x 1:1; o 24:1),
+                probabilities: Probabilities::from(&[1.0, 0.0, 0.0, 0.0, 0.0]),
+            })
+            .unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        // Without learning the loss never gets lower after the first epoch.
+        let model =
+            TDModel::<TrainingBackend>::new(ModelConfig::new().with_neurons(8), &device(true));
+        let config = SupervisedConfig::new()
+            .with_learning_rate(0.0)
+            .with_max_epochs(3)
+            .with_validation(0.0)
+            .with_patience(0);
+        let trainer = SupervisedTrainer::new(device(true), config);
+        trainer
+            .train(model, &[path], Some(dir.path().to_path_buf()))
+            .unwrap();
+
+        let losses = std::fs::read_to_string(dir.join("supervised.csv")).unwrap();
+        assert_eq!(losses.lines().count(), 4);
+    }
+}
@@ -4,202 +4,1141 @@ use std::{
 };
 
 use crate::{
+    benchmark::{play, Benchmark, BenchmarkConfig, BenchmarkRow, Opponent},
     dicegen::DiceGen,
-    duel::duel,
-    evaluator::{Evaluator, HyperEvaluator, PubEval, RandomEvaluator},
+    evaluator::{Evaluator, PositionEvaluator, PubEval, RandomEvaluator},
+    exploration::Exploration,
     fstate::FState,
+    league::League,
+    metrics::{weight_norm, MetricsFormat, MetricsWriter, TrainingStats},
+    start::{StartPositions, Starts},
 };
 use bkgm::{
+    Dice,
     GameState::{GameOver, Ongoing},
-    Hypergammon, State,
+    State,
 };
-use burn::{config::Config, module::Module, record::NoStdTrainingRecorder};
 use burn::{
-    optim::{momentum::MomentumConfig, GradientsParams, Optimizer, SgdConfig},
+    config::Config,
+    module::{AutodiffModule, ModuleMapper, ModuleVisitor, ParamId},
+    optim::GradientsParams,
     tensor::{backend::AutodiffBackend, Data, Tensor},
 };
+use rayon::prelude::*;
+use std::marker::PhantomData;
 
-use crate::{dicegen::FastrandDice, model::TDModel};
+use crate::{dicegen::FastrandDice, model::TDNetwork};
 
 #[derive(Config)]
 pub struct TDConfig {
     #[config(default = 0.1)]
     pub learning_rate: f64,
+    /// λ, how quickly the eligibility traces decay. 0 is TD(0), 1 is Monte Carlo.
     #[config(default = 0.7)]
-    pub td_decay: f64,
+    pub lambda: f64,
+    /// γ, the discount of the next position's value. Games always end, so 1 is fine.
+    #[config(default = 1.0)]
+    pub gamma: f64,
+    /// Threads playing self-play games in `TDTrainer::train_parallel`.
+    #[config(default = 4)]
+    pub workers: usize,
+    /// Games played with one snapshot of the model before the learner publishes new weights.
+    #[config(default = 32)]
+    pub sync_interval: usize,
+    /// Seeds the dice of every game from its episode number, so that a run can be repeated.
+    /// `train_parallel` then gives the same weights for any number of workers.
+    /// `TDTrainer::new` picks a random seed if there is none, it is saved with every checkpoint.
+    #[config(default = "None")]
+    pub seed: Option<u64>,
+    /// A checkpoint is saved after every game whose episode number is a multiple of this.
+    #[config(default = 1000)]
+    pub checkpoint_interval: usize,
+    /// Benchmarks and best model gating, results go to `benchmarks.csv` or `benchmarks.jsonl`
+    /// next to the checkpoints.
+    #[config(default = "None")]
+    pub benchmark: Option<BenchmarkConfig>,
+    /// Episodes per row of `metrics.csv` or `metrics.jsonl`, next to the checkpoints.
+    #[config(default = 1000)]
+    pub metrics_interval: usize,
+    #[config(default = "MetricsFormat::Csv")]
+    pub metrics_format: MetricsFormat,
+    /// How the model picks its moves in training games. After an exploratory move the state
+    /// before it learns from the greedy move instead, and the traces start again.
+    #[config(default = "Exploration::Greedy")]
+    pub exploration: Exploration,
+    /// Where training games start, the opening by default.
+    #[config(default = "StartPositions::Opening")]
+    pub start: StartPositions,
+}
+
+impl TDConfig {
+    /// Fails if `G` can't be trained with this configuration, training expects a valid one.
+    pub fn validate<G: Benchmark>(&self) -> Result<(), String> {
+        match &self.benchmark {
+            Some(benchmark) => benchmark.validate::<G>(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Eligibility traces of one game, one trace per output for every parameter.
+///
+/// After each position `s` the traces become `e_k = γλ e_k + ∇V_k(s)`, and the TD errors
+/// `δ_k = target_k - V_k(s)` update the parameters by `α Σ_k δ_k e_k`.
+pub struct EligibilityTraces<B: AutodiffBackend> {
+    traces: Vec<GradientsParams>,
+    decay: f64,
+    _backend: PhantomData<B>,
+}
+
+impl<B: AutodiffBackend> EligibilityTraces<B> {
+    pub fn new(config: &TDConfig) -> Self {
+        Self {
+            traces: Vec::new(),
+            decay: config.gamma * config.lambda,
+            _backend: PhantomData,
+        }
+    }
+
+    /// Forgets the earlier positions, their credit must not reach past an exploratory move.
+    pub fn reset(&mut self) {
+        self.traces.clear();
+    }
+
+    /// Decays the traces and adds the gradient of every output of `value`, returns the outputs.
+    /// Each output needs its own backward pass, so `value` is computed once per output.
+    pub fn accumulate<M: AutodiffModule<B>>(
+        &mut self,
+        model: &M,
+        value: impl Fn(&M) -> Tensor<B, 1>,
+    ) -> Vec<f32> {
+        let output = value(model);
+        let outputs = to_vec(output.clone());
+        if self.traces.is_empty() {
+            self.traces = (0..outputs.len()).map(|_| GradientsParams::new()).collect();
+        }
+
+        let mut output = Some(output);
+        for (k, trace) in self.traces.iter_mut().enumerate() {
+            let output = output.take().unwrap_or_else(|| value(model));
+            let grads = output.slice([k..k + 1]).sum().backward();
+            model.visit(&mut Accumulate::<B> {
+                trace,
+                grads: GradientsParams::from_grads(grads, model),
+                decay: self.decay,
+                _backend: PhantomData,
+            });
+        }
+        outputs
+    }
+
+    /// Moves every parameter by `learning_rate * Σ_k td_errors[k] * e_k`.
+    pub fn update<M: AutodiffModule<B>>(
+        &self,
+        model: M,
+        learning_rate: f64,
+        td_errors: &[f32],
+    ) -> M {
+        model.map(&mut Update::<B> {
+            traces: &self.traces,
+            steps: td_errors
+                .iter()
+                .map(|&td_error| learning_rate * td_error as f64)
+                .collect(),
+            _backend: PhantomData,
+        })
+    }
+}
+
+struct Accumulate<'a, B: AutodiffBackend> {
+    trace: &'a mut GradientsParams,
+    grads: GradientsParams,
+    decay: f64,
+    _backend: PhantomData<B>,
+}
+
+impl<B: AutodiffBackend> ModuleVisitor<B> for Accumulate<'_, B> {
+    fn visit<const D: usize>(&mut self, id: &ParamId, _tensor: &Tensor<B, D>) {
+        let trace = self.trace.remove::<B::InnerBackend, D>(id);
+        let grad = self.grads.remove::<B::InnerBackend, D>(id);
+        let trace = match (trace, grad) {
+            (Some(trace), Some(grad)) => trace.mul_scalar(self.decay) + grad,
+            (Some(trace), None) => trace.mul_scalar(self.decay),
+            (None, Some(grad)) => grad,
+            // Parameters which haven't influenced an output yet, like the other net of a MultiNet.
+            (None, None) => return,
+        };
+        self.trace.register::<B::InnerBackend, D>(id.clone(), trace);
+    }
+}
+
+struct Update<'a, B: AutodiffBackend> {
+    traces: &'a [GradientsParams],
+    steps: Vec<f64>,
+    _backend: PhantomData<B>,
+}
+
+impl<B: AutodiffBackend> ModuleMapper<B> for Update<'_, B> {
+    fn map<const D: usize>(&mut self, id: &ParamId, tensor: Tensor<B, D>) -> Tensor<B, D> {
+        let require_grad = tensor.is_require_grad();
+        let mut inner = tensor.inner();
+        for (trace, &step) in self.traces.iter().zip(&self.steps) {
+            if let Some(trace) = trace.get::<B::InnerBackend, D>(id) {
+                inner = inner + trace.mul_scalar(step);
+            }
+        }
+        let tensor = Tensor::from_inner(inner);
+        if require_grad {
+            tensor.require_grad()
+        } else {
+            tensor
+        }
+    }
+}
+
+fn to_vec<B: AutodiffBackend>(tensor: Tensor<B, 1>) -> Vec<f32> {
+    let data: Data<f32, 1> = tensor.into_data().convert();
+    data.value
+}
+
+/// Dice of one training game.
+fn dicegen(seed: Option<u64>, episode: usize) -> FastrandDice {
+    match seed {
+        Some(seed) => {
+            FastrandDice::with_seed(seed ^ (episode as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        }
+        None => FastrandDice::new(),
+    }
+}
+
+const EXPLORATION: u64 = 0;
+const START: u64 = 1;
+
+/// Random choices of one training game other than the dice, every `stream` has its own.
+fn game_rng(seed: Option<u64>, episode: usize, stream: u64) -> fastrand::Rng {
+    match seed {
+        Some(seed) => fastrand::Rng::with_seed(
+            seed ^ (episode as u64).wrapping_mul(0x94d0_49bb_1331_11eb)
+                ^ stream.wrapping_mul(0xd6e8_feb8_6659_fd93),
+        ),
+        None => fastrand::Rng::new(),
+    }
+}
+
+/// The opening has a special first roll, other starts are rolled like any other position.
+fn start_roll<G: State + Send>(start: &FState<G>, dicegen: &mut impl DiceGen) -> Dice {
+    if *start == FState::new() {
+        dicegen.first_roll()
+    } else {
+        dicegen.roll()
+    }
+}
+
+/// A training game, every position from the start to the finished game.
+struct Game<G: State> {
+    states: Vec<FState<G>>,
+    /// For every move, the greedy one if an exploratory move was played instead.
+    greedy: Vec<Option<FState<G>>>,
+    /// The side whose moves are learned, both sides if there is none.
+    learner: Option<bool>,
+}
+
+impl<G: State + Send> Game<G> {
+    /// The learned positions, each with the greedy alternative to the move which led to it if
+    /// that move was exploratory. With a learner these are its afterstates, the positions the
+    /// opponent is on roll in, and the end, so the greedy alternative is one of them as well.
+    fn learned(&self) -> Vec<(FState<G>, Option<FState<G>>)> {
+        self.states
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| match self.learner {
+                Some(side) => state.turn != side || state.game_state() != Ongoing,
+                None => true,
+            })
+            .map(|(i, state)| (*state, i.checked_sub(1).and_then(|i| self.greedy[i])))
+            .collect()
+    }
+}
+
+/// Game `episode` between `model` and `opponent`. Without a `learner` side `model` plays both
+/// sides, otherwise the side whose `turn` is `learner`. Only `model` explores.
+fn play_game<G, E, O>(
+    model: &E,
+    opponent: &O,
+    learner: Option<bool>,
+    exploration: &Exploration,
+    starts: &Starts<G>,
+    seed: Option<u64>,
+    episode: usize,
+) -> Game<G>
+where
+    G: State + Send,
+    E: PositionEvaluator<FState<G>>,
+    O: Evaluator<FState<G>>,
+{
+    let mut dicegen = dicegen(seed, episode);
+    let mut rng = game_rng(seed, episode, EXPLORATION);
+    let mut state = starts.pick(episode, &mut game_rng(seed, episode, START));
+    let mut dice = start_roll(&state, &mut dicegen);
+    let mut game = Game {
+        states: vec![state],
+        greedy: Vec::new(),
+        learner,
+    };
+    while state.game_state() == Ongoing {
+        let (next, greedy) = if learner.is_none() || learner == Some(state.turn) {
+            exploration.choose(model, &state, &dice, episode, &mut rng)
+        } else {
+            (opponent.best_position(&state, &dice), None)
+        };
+        dice = dicegen.roll();
+        game.states.push(next);
+        game.greedy.push(greedy);
+        state = next;
+    }
+    game
+}
+
+/// The best model of a training run and what is needed to benchmark against it.
+struct Gating<G: Benchmark, M> {
+    best: M,
+    best_ep: usize,
+    /// Episode from which the patience runs out, the last new best model or rollback.
+    since: usize,
+    hyper: Option<G::Hyper>,
+    benchmarks: Option<MetricsWriter>,
+}
+
+/// Statistics of a training run and where they are written.
+struct Metrics {
+    stats: TrainingStats,
+    writer: Option<MetricsWriter>,
 }
 
 pub struct TDTrainer<B: AutodiffBackend> {
     device: B::Device,
-    optim: SgdConfig,
     config: TDConfig,
+    /// The next game to play.
+    episode: usize,
 }
 
 impl<B: AutodiffBackend> TDTrainer<B> {
     pub fn new(device: B::Device, config: TDConfig) -> Self {
-        let optim = SgdConfig::new().with_momentum(Some(
-            MomentumConfig::new()
-                .with_dampening(0.0)
-                .with_momentum(config.td_decay),
-        ));
+        let seed = config.seed.unwrap_or_else(|| fastrand::u64(..));
         Self {
             device,
-            optim,
-            config,
+            config: config.with_seed(Some(seed)),
+            episode: 0,
         }
     }
 
-    fn get_value<G: State + Send>(&self, state: &FState<G>, model: &TDModel<B>) -> Tensor<B, 1> {
+    pub fn config(&self) -> &TDConfig {
+        &self.config
+    }
+
+    pub fn episode(&self) -> usize {
+        self.episode
+    }
+
+    /// Continues a run from one of its checkpoints: the model, the episode and the training
+    /// configuration, including the seed, are taken from it. Only `workers` is kept, it doesn't
+    /// change the results. Traces start empty with every game and plain gradient steps have no
+    /// state, so the run continues exactly as if it had never stopped.
+    pub fn resume<M: TDNetwork<B>>(&mut self, path: &PathBuf) -> Result<M, String> {
+        let (model, info) = M::load_checkpoint(path, &self.device)?;
+        let config = info
+            .training
+            .ok_or_else(|| format!("{} wasn't saved by TDTrainer", path.display()))?;
+        self.config = config.with_workers(self.config.workers);
+        self.episode = info.episode;
+        Ok(model)
+    }
+
+    fn get_value<G: State + Send, M: TDNetwork<B>>(
+        &self,
+        state: &FState<G>,
+        model: &M,
+    ) -> Tensor<B, 1> {
         let state = if state.turn { *state } else { state.flip() };
         match state.game_state() {
-            GameOver(result) => model.from_result(result, &self.device),
-            Ongoing => model.forward_pos(state, &self.device),
+            GameOver(result) => model.from_result(result),
+            Ongoing => model.forward_pos(state),
+        }
+    }
+
+    /// The result of a finished game, otherwise γ times the value of `state`.
+    fn target<G: State + Send, M: TDNetwork<B>>(&self, state: &FState<G>, model: &M) -> Vec<f32> {
+        let value = to_vec(self.get_value(state, model).detach());
+        match state.game_state() {
+            GameOver(_) => value,
+            Ongoing => value
+                .into_iter()
+                .map(|v| (self.config.gamma * v as f64) as f32)
+                .collect(),
         }
     }
 
-    fn train_game<G: State + Send>(&mut self, model: TDModel<B>) -> TDModel<B> {
-        let mut optim = self.optim.init();
+    /// One TD(λ) step for the move from `state` to `next`.
+    fn step<G, M>(
+        &self,
+        traces: &mut EligibilityTraces<B>,
+        model: M,
+        state: &FState<G>,
+        next: &FState<G>,
+        stats: &mut TrainingStats,
+    ) -> M
+    where
+        G: State + Send,
+        M: TDNetwork<B> + AutodiffModule<B>,
+    {
+        let cur_value = traces.accumulate(&model, |model| self.get_value(state, model));
+        let td_errors: Vec<f32> = self
+            .target(next, &model)
+            .iter()
+            .zip(&cur_value)
+            .map(|(target, cur)| target - cur)
+            .collect();
+        stats.add_td_errors(&td_errors);
+        traces.update(model, self.config.learning_rate, &td_errors)
+    }
+
+    /// Plays a game with the model being trained, every move uses the latest weights.
+    fn train_game<G, M>(
+        &mut self,
+        model: M,
+        starts: &Starts<G>,
+        ep: usize,
+        stats: &mut TrainingStats,
+    ) -> M
+    where
+        G: State + Send,
+        M: TDNetwork<B> + AutodiffModule<B> + PositionEvaluator<FState<G>>,
+    {
+        let mut traces = EligibilityTraces::new(&self.config);
         let mut model = model;
 
-        let mut dicegen = FastrandDice::new();
-        let mut dice = dicegen.first_roll();
-        let mut state = FState::<G>::new();
+        let seed = self.config.seed;
+        let mut dicegen = dicegen(seed, ep);
+        let mut rng = game_rng(seed, ep, EXPLORATION);
+        let mut state = starts.pick(ep, &mut game_rng(seed, ep, START));
+        let mut dice = start_roll(&state, &mut dicegen);
+        let mut moves = 0;
 
         while state.game_state() == Ongoing {
-            let cur_value = self.get_value(&state, &model);
-            let grads = GradientsParams::from_grads(cur_value.backward(), &model);
-            state = model.best_position(&state, &dice);
+            let (next, greedy) = self
+                .config
+                .exploration
+                .choose(&model, &state, &dice, ep, &mut rng);
             dice = dicegen.roll();
-            let next_value = self.get_value(&state, &model);
-            let td_error = next_value - cur_value.clone();
-            let data: Data<f64, 1> = td_error.to_data().convert();
-            let td_error = data.value[0];
-            model = optim.step(-self.config.learning_rate * td_error, model, grads);
+            let target = greedy.as_ref().unwrap_or(&next);
+            model = self.step(&mut traces, model, &state, target, stats);
+            if greedy.is_some() {
+                traces.reset();
+            }
+            state = next;
+            moves += 1;
+        }
+
+        if let GameOver(result) = state.game_state() {
+            stats.add_game(moves, result);
         }
+        model
+    }
 
+    /// Learns from a game played by an earlier snapshot of the model. With a `learner` side
+    /// only the positions after its own moves are learned, each from the next one, so the
+    /// opponent's moves are part of the environment and its choices drive no updates.
+    fn learn_game<G, M>(&self, model: M, game: &Game<G>, stats: &mut TrainingStats) -> M
+    where
+        G: State + Send,
+        M: TDNetwork<B> + AutodiffModule<B>,
+    {
+        let mut traces = EligibilityTraces::new(&self.config);
+        let mut model = model;
+        for pair in game.learned().windows(2) {
+            let (state, _) = &pair[0];
+            let (next, greedy) = &pair[1];
+            let target = greedy.as_ref().unwrap_or(next);
+            model = self.step(&mut traces, model, state, target, stats);
+            if greedy.is_some() {
+                traces.reset();
+            }
+        }
+        if let Some(GameOver(result)) = game.states.last().map(|state| state.game_state()) {
+            stats.add_game(game.states.len() - 1, result);
+        }
         model
     }
 
-    pub fn train<G: State + Send>(
+    fn starts<G: State + Send>(&self) -> Starts<G> {
+        self.config
+            .start
+            .load()
+            .expect("Failed to load start positions")
+    }
+
+    fn is_checkpoint(&self, ep: usize) -> bool {
+        ep % self.config.checkpoint_interval.max(1) == 0
+    }
+
+    fn is_benchmark(&self, ep: usize) -> bool {
+        match &self.config.benchmark {
+            Some(benchmark) => ep > 0 && ep % benchmark.interval.max(1) == 0,
+            None => false,
+        }
+    }
+
+    /// A resumed run continues with the best model of the original one.
+    fn start_gating<G, M>(&self, path: &Option<PathBuf>, model: &M) -> Gating<G, M>
+    where
+        G: Benchmark,
+        M: TDNetwork<B>,
+    {
+        let resumed = match path {
+            Some(path) if self.episode > 0 && self.config.benchmark.is_some() => {
+                M::load_checkpoint(&path.join("best"), &self.device).ok()
+            }
+            _ => None,
+        };
+        let (best, best_ep) = match resumed {
+            Some((best, info)) => (best, info.episode.saturating_sub(1)),
+            None => (model.clone(), self.episode),
+        };
+        let hyper = match &self.config.benchmark {
+            Some(benchmark) if benchmark.opponents.contains(&Opponent::Hyper) => {
+                Some(G::hyper().expect("Failed to load the hyper opponent"))
+            }
+            _ => None,
+        };
+        let benchmarks = match (path, &self.config.benchmark) {
+            (Some(path), Some(_)) => Some(
+                MetricsWriter::open(path, "benchmarks", self.config.metrics_format)
+                    .expect("Failed to benchmark"),
+            ),
+            _ => None,
+        };
+        Gating {
+            best,
+            best_ep,
+            since: best_ep,
+            hyper,
+            benchmarks,
+        }
+    }
+
+    fn start_metrics(&self, path: &Option<PathBuf>) -> Metrics {
+        let writer = path.as_ref().map(|path| {
+            MetricsWriter::open(path, "metrics", self.config.metrics_format)
+                .expect("Failed to open metrics")
+        });
+        Metrics {
+            stats: TrainingStats::default(),
+            writer,
+        }
+    }
+
+    fn is_metrics(&self, ep: usize) -> bool {
+        ep % self.config.metrics_interval.max(1) == 0
+    }
+
+    /// Plays the benchmarks due after game `ep`, returns the model to continue with.
+    /// That is the best model if the patience has run out.
+    fn benchmark<G, M>(
+        &self,
+        path: &Option<PathBuf>,
+        model: M,
+        ep: usize,
+        gating: &mut Gating<G, M>,
+        stats: &mut TrainingStats,
+    ) -> M
+    where
+        G: Benchmark,
+        M: TDNetwork<B> + AutodiffModule<B>,
+        M::InnerModule: Evaluator<FState<G>>,
+    {
+        let config = match &self.config.benchmark {
+            Some(config) if self.is_benchmark(ep) => config,
+            _ => return model,
+        };
+        let seed = self.config.seed.unwrap_or_default() ^ ep as u64;
+        let snapshot = model.valid();
+        let mut results = Vec::new();
+        let mut new_best = false;
+        for &opponent in &config.opponents {
+            let probs = match opponent {
+                Opponent::Random => play(&snapshot, RandomEvaluator::new(), config.games, seed),
+                Opponent::PubEval => {
+                    play(&snapshot, PubEval::<FState<G>>::new(), config.games, seed)
+                }
+                Opponent::Hyper => play(
+                    &snapshot,
+                    gating.hyper.as_ref().unwrap(),
+                    config.games,
+                    seed,
+                ),
+                Opponent::Best => {
+                    let probs = play(&snapshot, gating.best.valid(), config.games, seed);
+                    new_best = probs.win_prob() > config.gate;
+                    probs
+                }
+            };
+            stats.add_benchmark(opponent, probs.equity());
+            results.push((opponent, probs));
+        }
+
+        let mut rollback = false;
+        let model = if new_best {
+            gating.best = model.clone();
+            gating.best_ep = ep;
+            gating.since = ep;
+            if let Some(path) = path {
+                let info = model
+                    .checkpoint_info()
+                    .with_training(Some(self.config.clone()))
+                    .with_episode(ep + 1);
+                model
+                    .clone()
+                    .save(path.join("best"), info)
+                    .expect("Failed to save model");
+            }
+            model
+        } else if config
+            .patience
+            .is_some_and(|patience| ep - gating.since >= patience)
+        {
+            rollback = true;
+            gating.since = ep;
+            gating.best.clone()
+        } else {
+            model
+        };
+
+        if let Some(writer) = &mut gating.benchmarks {
+            for (opponent, probs) in results {
+                let row = BenchmarkRow::new(ep, opponent, &probs, gating.best_ep, rollback);
+                writer.write(&row).expect("Failed to write benchmarks");
+            }
+        }
+        model
+    }
+
+    /// Benchmarks, writes the metrics of the interval, then saves a checkpoint of the model
+    /// training continues with.
+    fn after_game<G, M>(
+        &self,
+        path: &Option<PathBuf>,
+        model: M,
+        ep: usize,
+        gating: &mut Gating<G, M>,
+        metrics: &mut Metrics,
+    ) -> M
+    where
+        G: Benchmark,
+        M: TDNetwork<B> + AutodiffModule<B>,
+        M::InnerModule: Evaluator<FState<G>>,
+    {
+        let model = self.benchmark(path, model, ep, gating, &mut metrics.stats);
+        if self.is_metrics(ep) {
+            let row = metrics.stats.take_row(ep, weight_norm(&model.valid()));
+            println!(
+                "Episode {}: td error {:.4}, {:.1} moves, {:.1}% gammons, {:.1} episodes/s",
+                ep,
+                row.mean_abs_td_error,
+                row.mean_game_length,
+                row.gammon_rate * 100.0,
+                row.episodes_per_sec
+            );
+            if let Some(writer) = &mut metrics.writer {
+                writer.write(&row).expect("Failed to write metrics");
+            }
+        }
+        if self.is_checkpoint(ep) {
+            self.checkpoint(path, &model, ep);
+        }
+        model
+    }
+
+    /// Saves the model after game `ep`, the checkpoint resumes with the next one.
+    fn checkpoint<M: TDNetwork<B>>(&self, path: &Option<PathBuf>, model: &M, ep: usize) {
+        if let Some(path) = path {
+            stdout().flush().unwrap();
+            let info = model
+                .checkpoint_info()
+                .with_training(Some(self.config.clone()))
+                .with_episode(ep + 1);
+            model
+                .clone()
+                .save(
+                    PathBuf::from(format!("{}/games-{}", path.display(), ep)),
+                    info,
+                )
+                .expect("Failed to save model");
+        }
+    }
+
+    /// Last game of the batch starting with game `ep`. Batches also end at checkpoints and
+    /// benchmarks, so that a resumed run publishes the same snapshots as the original one.
+    fn batch_end(&self, ep: usize, num_episodes: usize) -> usize {
+        let interval = self.config.sync_interval.max(1);
+        let mut end = ep;
+        while end < num_episodes
+            && !self.is_checkpoint(end)
+            && !self.is_benchmark(end)
+            && (end + 1) % interval != 0
+        {
+            end += 1;
+        }
+        end
+    }
+
+    /// Self-play games are played by `workers` threads, each with a snapshot of the model.
+    /// The learner applies the TD updates of each batch in episode order,
+    /// then publishes the new weights as the next snapshot.
+    pub fn train_parallel<G, M>(
         &mut self,
         path: Option<PathBuf>,
-        model: TDModel<B>,
+        model: M,
         num_episodes: usize,
-    ) -> TDModel<B> {
-        // self.train_one(model.clone());
-        let mut model = model;
-        let mut best_model = model.clone();
-        let mut best_ep = 0;
-        let mut ep = 0;
+    ) -> M
+    where
+        G: Benchmark,
+        M: TDNetwork<B> + AutodiffModule<B>,
+        M::InnerModule: PositionEvaluator<FState<G>> + Send,
+    {
+        let seed = self.config.seed;
+        let exploration = self.config.exploration.clone();
+        let starts = self.starts::<G>();
+        self.run_workers::<G, M>(path, model, num_episodes, |snapshot, episode| {
+            play_game(
+                snapshot,
+                snapshot,
+                None,
+                &exploration,
+                &starts,
+                seed,
+                episode,
+            )
+        })
+    }
+
+    /// Like `train_parallel`, but the model plays against a member of `league` in every game,
+    /// for example `PubEval` or frozen checkpoints loaded with `League::load`.
+    /// The model alternates sides and only the positions after its own moves are learned.
+    pub fn train_against<G, M, E>(
+        &mut self,
+        path: Option<PathBuf>,
+        model: M,
+        league: &League<E>,
+        num_episodes: usize,
+    ) -> M
+    where
+        G: Benchmark,
+        M: TDNetwork<B> + AutodiffModule<B>,
+        M::InnerModule: PositionEvaluator<FState<G>> + Send,
+        E: Evaluator<FState<G>> + Sync,
+    {
+        let seed = self.config.seed;
+        let pick_seed = seed.unwrap_or_default();
+        let exploration = self.config.exploration.clone();
+        let starts = self.starts::<G>();
+        self.run_workers::<G, M>(path, model, num_episodes, |snapshot, episode| {
+            let learner = Some(episode % 2 == 0);
+            let opponent = league.pick(pick_seed, episode);
+            play_game(
+                snapshot,
+                opponent,
+                learner,
+                &exploration,
+                &starts,
+                seed,
+                episode,
+            )
+        })
+    }
+
+    /// Plays the games of each batch with `play` on the worker threads,
+    /// then learns from them in episode order.
+    fn run_workers<G, M>(
+        &mut self,
+        path: Option<PathBuf>,
+        model: M,
+        num_episodes: usize,
+        play: impl Fn(&M::InnerModule, usize) -> Game<G> + Sync,
+    ) -> M
+    where
+        G: Benchmark,
+        M: TDNetwork<B> + AutodiffModule<B>,
+        M::InnerModule: PositionEvaluator<FState<G>> + Send,
+    {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.config.workers)
+            .build()
+            .expect("Failed to start workers");
+        let mut model = model.fork(&self.device);
+        let mut gating = self.start_gating::<G, M>(&path, &model);
+        let mut metrics = self.start_metrics(&path);
+        let mut ep = self.episode;
 
         while ep <= num_episodes {
-            model = self.train_game::<G>(model.clone());
-            if ep % 1000 == 0 {
-                println!("Episode: {}", ep);
-                match path {
-                    Some(ref path) => {
-                        stdout().flush().unwrap();
-                        model
-                            .clone()
-                            .save_file(
-                                format!("{}/games-{}", path.display(), ep),
-                                &NoStdTrainingRecorder::new(),
-                            )
-                            .expect("Failed to save model");
-                    }
-                    None => (),
-                }
+            let end = self.batch_end(ep, num_episodes);
+            let snapshot = model.valid();
+            let games: Vec<Game<G>> = pool.install(|| {
+                (ep..=end)
+                    .into_par_iter()
+                    .map_with(snapshot, |snapshot, episode| play(&*snapshot, episode))
+                    .collect()
+            });
+            for game in games {
+                model = self.learn_game(model, &game, &mut metrics.stats);
+                model = self.after_game(&path, model, ep, &mut gating, &mut metrics);
+                ep += 1;
             }
+        }
+        self.episode = ep;
+        model
+    }
 
-            ep += 1;
+    /// Self-play training of a `TDModel`, `MultiNet` or any other `TDNetwork`.
+    pub fn train<G, M>(&mut self, path: Option<PathBuf>, model: M, num_episodes: usize) -> M
+    where
+        G: Benchmark,
+        M: TDNetwork<B> + AutodiffModule<B> + PositionEvaluator<FState<G>>,
+        M::InnerModule: Evaluator<FState<G>>,
+    {
+        // Evaluations happen where the parameters are, so this moves the whole game there.
+        let mut model = model.fork(&self.device);
+        let mut gating = self.start_gating::<G, M>(&path, &model);
+        let mut metrics = self.start_metrics(&path);
+        let starts = self.starts::<G>();
+        let mut ep = self.episode;
 
-            // if ep % 5_000 == 0 {
-            //     let probs = duel::<FState<G>>(model.clone(), RandomEvaluator::new(), 1000);
-            //     println!(
-            //         "Random Equity: {:.3} ({:.1}%). {:?}",
-            //         probs.equity(),
-            //         probs.win_prob() * 100.0,
-            //         probs,
-            //     );
-            //     let probs = duel::<FState<G>>(model.clone(), best_model.clone(), 1000);
-            //     println!(
-            //         "Prev Equity: {:.3} ({:.1}%). {:?}",
-            //         probs.equity(),
-            //         probs.win_prob() * 100.0,
-            //         probs,
-            //     );
-            //     if probs.win_prob() > 0.53 {
-            //         best_model = model.clone();
-            //         best_ep = ep;
-            //     }
-            // }
-
-            // if ep - best_ep > 100_000 {
-            //     println!("No improvement. Reseting.");
-            //     model = best_model.clone();
-            //     ep = best_ep;
-            // }
-
-            // if ep % 25_000 == 0 {
-            //     let probs = duel::<FState<Hypergammon>>(
-            //         model.clone(),
-            //         HyperEvaluator::new().unwrap(),
-            //         1000,
-            //     );
-            //     println!(
-            //         "Hyper Equity: {:.3} ({:.1}%). {:?}",
-            //         probs.equity(),
-            //         probs.win_prob() * 100.0,
-            //         probs,
-            //     );
-            //     let probs = duel::<FState<Hypergammon>>(model.clone(), PubEval::new(), 1000);
-            //     println!(
-            //         "Pub Eval Equity: {:.3} ({:.1}%). {:?}",
-            //         probs.equity(),
-            //         probs.win_prob() * 100.0,
-            //         probs,
-            //     );
-            //     // let probs = duel::<FState<G>>(model.clone(), prev_model.clone(), 1000);
-            //     // println!(
-            //     //     "Prev Equity: {:.3} ({:.1}%). {:?}",
-            //     //     probs.equity(),
-            //     //     probs.win_prob() * 100.0,
-            //     //     probs,
-            //     // );
-            //     // if probs.win_prob() < 0.5 {
-            //     //     model = prev_model.clone();
-            //     //     println!("Not improving");
-            //     // } else {
-            //     //     println!("Saving model");
-            //     //     model
-            //     //         .clone()
-            //     //         .save_file(
-            //     //             format!("model/td-next/games-{}", ep),
-            //     //             &NoStdTrainingRecorder::new(),
-            //     //         )
-            //     //         .expect("Failed to save model");
-            //     // }
-            //     // prev_model = model.clone();
-            // }
-            // if ep % 2_000 == 0 {
-            //     println!("Saving model");
-            //     model
-            //         .clone()
-            //         .save_file(
-            //             format!("model/td/games-{}", ep),
-            //             &NoStdTrainingRecorder::new(),
-            //         )
-            //         .expect("Failed to save model");
-            //     let probs = duel::duel::<FState<Hypergammon>>(model.clone(), prev_model, 1000);
-            //     println!(
-            //         "Equity: {:.3} ({:.1}%). {:?}",
-            //         probs.equity(),
-            //         probs.win_prob() * 100.0,
-            //         probs,
-            //     );
-            //     prev_model = model.clone();
-            // }
+        while ep <= num_episodes {
+            model = self.train_game::<G, M>(model, &starts, ep, &mut metrics.stats);
+            model = self.after_game(&path, model, ep, &mut gating, &mut metrics);
+            ep += 1;
         }
+        self.episode = ep;
         model
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{device, TrainingBackend};
+    use crate::benchmark::{BenchmarkConfig, Opponent};
+    use crate::evaluator::PubEval;
+    use crate::exploration::{Exploration, Schedule};
+    use crate::fstate::FState;
+    use crate::league::League;
+    use crate::metrics::TrainingStats;
+    use crate::model::{ModelConfig, TDModel};
+    use crate::start::StartPositions;
+    use crate::test_dir::TestDir;
+    use crate::train::{play_game, EligibilityTraces, Game, TDConfig, TDTrainer};
+    use crate::weights::Weights;
+    use bkgm::{GameState::Ongoing, Hypergammon};
+    use burn::module::{AutodiffModule, Module, Param};
+    use burn::nn::{Linear, LinearConfig};
+    use burn::tensor::{Data, Shape, Tensor};
+
+    /// A value table, state `i` is the one-hot input `i`.
+    #[derive(Module, Debug)]
+    struct Table<B: burn::tensor::backend::Backend> {
+        values: Linear<B>,
+    }
+
+    impl Table<TrainingBackend> {
+        fn new(values: Vec<f32>, outputs: usize) -> Self {
+            let states = values.len() / outputs;
+            let device = device(true);
+            let mut linear = LinearConfig::new(states, outputs)
+                .with_bias(false)
+                .init(&device);
+            let data = Data::new(values, Shape::new([states, outputs]));
+            linear.weight = Param::from(Tensor::from_data(data.convert(), &device));
+            Self { values: linear }
+        }
+
+        fn value(&self, state: usize) -> Tensor<TrainingBackend, 1> {
+            let [states, outputs] = self.values.weight.shape().dims;
+            let mut input = vec![0.0; states];
+            input[state] = 1.0;
+            let data = Data::new(input, Shape::new([1, states]));
+            let input = Tensor::from_data(data.convert(), &device(true));
+            self.values.forward(input).reshape([outputs])
+        }
+
+        fn values(&self) -> Vec<f32> {
+            let data: Data<f32, 2> = self.values.weight.val().into_data().convert();
+            data.value
+        }
+    }
+
+    /// Plays the chain 0 -> 1 -> end, the end pays `reward`.
+    fn chain(config: TDConfig, table: Table<TrainingBackend>, reward: &[f32]) -> Vec<f32> {
+        let mut traces = EligibilityTraces::new(&config);
+        let mut table = table;
+        for state in 0..2 {
+            let cur = traces.accumulate(&table, |table| table.value(state));
+            let target: Vec<f32> = if state == 0 {
+                let data: Data<f32, 1> = table.value(1).into_data().convert();
+                data.value.iter().map(|v| config.gamma as f32 * v).collect()
+            } else {
+                reward.to_vec()
+            };
+            let td_errors: Vec<f32> = target.iter().zip(&cur).map(|(t, c)| t - c).collect();
+            table = traces.update(table, config.learning_rate, &td_errors);
+        }
+        table.values()
+    }
+
+    fn assert_close(actual: Vec<f32>, expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn td_lambda() {
+        let config = TDConfig::new()
+            .with_learning_rate(0.1)
+            .with_lambda(0.5)
+            .with_gamma(0.9);
+        // δ1 = 0.9 * 0.2 - 0.5 = -0.32, so V(0) = 0.5 - 0.032
+        // e = (0.45, 1), δ2 = 1 - 0.2 = 0.8, so V += 0.08 * (0.45, 1)
+        let values = chain(config, Table::new(vec![0.5, 0.2], 1), &[1.0]);
+        assert_close(values, &[0.504, 0.28]);
+    }
+
+    #[test]
+    fn td_zero() {
+        let config = TDConfig::new()
+            .with_learning_rate(0.1)
+            .with_lambda(0.0)
+            .with_gamma(0.9);
+        // Without traces the second error only changes V(1)
+        let values = chain(config, Table::new(vec![0.5, 0.2], 1), &[1.0]);
+        assert_close(values, &[0.468, 0.28]);
+    }
+
+    #[test]
+    fn outputs_have_their_own_errors() {
+        let config = TDConfig::new()
+            .with_learning_rate(0.1)
+            .with_lambda(0.5)
+            .with_gamma(0.9);
+        // Rows are states, columns outputs. The second output pays nothing at the end:
+        // δ1 = 0.9 * 0.3 - 0.1 = 0.17, so V(0) = 0.1 + 0.017
+        // e = (0.45, 1), δ2 = 0 - 0.3, so V -= 0.03 * (0.45, 1)
+        let table = Table::new(vec![0.5, 0.1, 0.2, 0.3], 2);
+        let values = chain(config, table, &[1.0, 0.0]);
+        assert_close(values, &[0.504, 0.1035, 0.28, 0.27]);
+    }
+
+    fn small_model() -> TDModel<TrainingBackend> {
+        TDModel::new(ModelConfig::new().with_neurons(8), &device(true))
+    }
+
+    /// The weights `train` ends with, which must be the same with 1 and with 3 workers.
+    fn reproducible(
+        config: TDConfig,
+        train: impl Fn(&mut TDTrainer<TrainingBackend>) -> TDModel<TrainingBackend>,
+    ) -> Weights {
+        let [one, three] = [1, 3].map(|workers| {
+            let mut trainer = TDTrainer::new(device(true), config.clone().with_workers(workers));
+            Weights::from_model(&train(&mut trainer))
+        });
+        assert_eq!(one, three);
+        one
+    }
+
+    #[test]
+    fn parallel_training_is_reproducible() {
+        let model = small_model();
+        let config = TDConfig::new().with_sync_interval(2).with_seed(Some(7));
+        reproducible(config, |trainer| {
+            trainer.train_parallel::<Hypergammon, _>(None, model.clone(), 4)
+        });
+    }
+
+    #[test]
+    fn batches_are_played_by_one_snapshot() {
+        let model = small_model();
+        let config = TDConfig::new()
+            .with_workers(2)
+            .with_sync_interval(4)
+            .with_seed(Some(9));
+        let mut trainer = TDTrainer::new(device(true), config.clone());
+        let trained = trainer.train_parallel::<Hypergammon, _>(None, model.clone(), 3);
+
+        // The checkpoint after game 0 ends the first batch, games 1 to 3 are the second one.
+        let learner = TDTrainer::<TrainingBackend>::new(device(true), config);
+        let starts = learner.starts::<Hypergammon>();
+        let mut stats = TrainingStats::default();
+        let mut expected = model;
+        for batch in [0..=0, 1..=3] {
+            let snapshot = expected.valid();
+            for episode in batch {
+                let game = play_game(
+                    &snapshot,
+                    &snapshot,
+                    None,
+                    &Exploration::Greedy,
+                    &starts,
+                    Some(9),
+                    episode,
+                );
+                expected = learner.learn_game(expected, &game, &mut stats);
+            }
+        }
+        assert_eq!(
+            Weights::from_model(&trained),
+            Weights::from_model(&expected)
+        );
+    }
+
+    #[test]
+    fn training_against_pubeval() {
+        let model = small_model();
+        let league = League::single(PubEval::<FState<Hypergammon>>::new());
+        let trained = reproducible(TDConfig::new().with_seed(Some(3)), |trainer| {
+            trainer.train_against::<Hypergammon, _, _>(None, model.clone(), &league, 4)
+        });
+        assert_ne!(trained, Weights::from_model(&model));
+    }
+
+    #[test]
+    fn only_the_learners_afterstates_are_learned() {
+        let model = small_model().valid();
+        let pubeval = PubEval::<FState<Hypergammon>>::new();
+        let starts = StartPositions::Opening.load().unwrap();
+        for learner in [true, false] {
+            let game = play_game(
+                &model,
+                &pubeval,
+                Some(learner),
+                &Exploration::Greedy,
+                &starts,
+                Some(3),
+                learner as usize,
+            );
+            let learned: Vec<_> = game.learned().into_iter().map(|(state, _)| state).collect();
+            // Only the end of the game is learned after an opponent's move, it is the reward.
+            for (state, next) in game.states.iter().zip(&game.states[1..]) {
+                let learned_next = state.turn == learner || next.game_state() != Ongoing;
+                assert_eq!(learned.contains(next), learned_next);
+            }
+            assert_eq!(learned.last(), game.states.last());
+        }
+    }
+
+    #[test]
+    fn exploration_is_reproducible() {
+        let model = small_model();
+        let league = League::single(PubEval::<FState<Hypergammon>>::new());
+        let train = |exploration| {
+            let config = TDConfig::new()
+                .with_seed(Some(5))
+                .with_exploration(exploration);
+            reproducible(config, |trainer| {
+                trainer.train_against::<Hypergammon, _, _>(None, model.clone(), &league, 4)
+            })
+        };
+        let epsilon = Exploration::EpsilonGreedy {
+            epsilon: Schedule::constant(0.5),
+        };
+        assert_ne!(train(epsilon), train(Exploration::Greedy));
+    }
+
+    #[test]
+    fn exploratory_moves_are_learned_from_the_greedy_move() {
+        let model = small_model();
+        let snapshot = model.valid();
+        let pubeval = PubEval::<FState<Hypergammon>>::new();
+        let starts = StartPositions::Opening.load().unwrap();
+        let epsilon = Exploration::EpsilonGreedy {
+            epsilon: Schedule::constant(0.5),
+        };
+        // A game with an exploratory move of the learner after its first learned position.
+        let (game, explored) = (0..)
+            .find_map(|episode| {
+                let game = play_game(
+                    &snapshot,
+                    &pubeval,
+                    Some(true),
+                    &epsilon,
+                    &starts,
+                    Some(5),
+                    episode,
+                );
+                let explored = (1..game.greedy.len()).find(|&i| game.greedy[i].is_some())?;
+                Some((game, explored))
+            })
+            .unwrap();
+
+        // The game up to that move, and the same with the greedy move played instead.
+        let mut states = game.states[..=explored + 1].to_vec();
+        let mut greedy = game.greedy[..=explored].to_vec();
+        let explored_game = Game {
+            states: states.clone(),
+            greedy: greedy.clone(),
+            learner: Some(true),
+        };
+        states[explored + 1] = greedy[explored].take().unwrap();
+        let greedy_game = Game {
+            states,
+            greedy,
+            learner: Some(true),
+        };
+
+        let trainer = TDTrainer::<TrainingBackend>::new(device(true), TDConfig::new());
+        let mut stats = TrainingStats::default();
+        let explored = trainer.learn_game(model.clone(), &explored_game, &mut stats);
+        let greedy = trainer.learn_game(model, &greedy_game, &mut stats);
+        assert_eq!(Weights::from_model(&explored), Weights::from_model(&greedy));
+    }
+
+    #[test]
+    fn resume_continues_the_run() {
+        let dir = TestDir::new("resume");
+        let config = TDConfig::new()
+            .with_workers(2)
+            .with_sync_interval(3)
+            .with_checkpoint_interval(2);
+        let model =
+            TDModel::<TrainingBackend>::new(ModelConfig::new().with_neurons(8), &device(true));
+        let mut trainer = TDTrainer::new(device(true), config);
+        let expected =
+            trainer.train_parallel::<Hypergammon, _>(Some(dir.path().to_path_buf()), model, 4);
+
+        let mut resumed = TDTrainer::<TrainingBackend>::new(device(true), TDConfig::new());
+        let model: TDModel<TrainingBackend> = resumed.resume(&dir.join("games-2")).unwrap();
+        assert_eq!(resumed.episode(), 3);
+        assert_eq!(resumed.config().seed, trainer.config().seed);
+        let model = resumed.train_parallel::<Hypergammon, _>(None, model, 4);
+        assert_eq!(Weights::from_model(&model), Weights::from_model(&expected));
+    }
+
+    #[test]
+    fn benchmarks_are_written_to_the_metrics_file() {
+        let dir = TestDir::new("benchmarks");
+        let benchmark = BenchmarkConfig::new()
+            .with_interval(2)
+            .with_games(2)
+            .with_opponents(vec![Opponent::Random, Opponent::Best])
+            .with_patience(Some(2));
+        let config = TDConfig::new()
+            .with_workers(2)
+            .with_benchmark(Some(benchmark));
+        let mut trainer = TDTrainer::new(device(true), config);
+        trainer.train_parallel::<Hypergammon, _>(Some(dir.path().to_path_buf()), small_model(), 4);
+
+        let metrics = std::fs::read_to_string(dir.join("benchmarks.csv")).unwrap();
+        let lines: Vec<&str> = metrics.lines().collect();
+        assert!(lines[0].starts_with("episode,opponent,equity"));
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].starts_with("2,random,"));
+        assert!(lines[4].starts_with("4,best,"));
+    }
+}
@@ -0,0 +1,387 @@
+//! Weights of a `TDModel` in a format which doesn't depend on burn.
+//!
+//! The JSON format is a single object:
+//!
+//! ```json
+//! {
+//!   "format": "td-gammon-weights",
+//!   "version": 1,
+//!   "activation": "sigmoid",
+//!   "encoding": "standard",
+//!   "layers": [{ "inputs": 202, "outputs": 160, "weights": [...], "bias": [...] }, ...]
+//! }
+//! ```
+//!
+//! The binary format holds the same values, all numbers little endian:
+//!
+//! | field      | type                                             |
+//! |------------|--------------------------------------------------|
+//! | magic      | the 4 bytes `TDGW`                               |
+//! | version    | u32                                              |
+//! | activation | u8 length, then the name in ASCII                |
+//! | encoding   | u8 length, then the name in ASCII                |
+//! | layers     | u32                                              |
+//! | per layer  | u32 inputs, u32 outputs, f32 weights, f32 bias   |
+//!
+//! Layers are the hidden layers followed by the output layer. Their `weights` are `inputs` rows
+//! of `outputs` values, row `i` holds the weights from input `i`. Hidden layers use `activation`,
+//! the output layer always uses a sigmoid. The outputs are either the win probability alone or
+//! the five GNU style probabilities (win, win_g, win_b, lose_g, lose_b).
+
+use crate::cpu::{CpuModel, Layer};
+use crate::inputs::{Encoder, Encoding};
+use crate::model::{Activation, TDModel};
+use burn::{
+    module::Param,
+    nn,
+    tensor::{backend::Backend, Data, Shape, Tensor},
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+
+const FORMAT: &str = "td-gammon-weights";
+const MAGIC: &[u8; 4] = b"TDGW";
+pub const WEIGHTS_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayerWeights {
+    pub inputs: usize,
+    pub outputs: usize,
+    /// `inputs` rows of `outputs` values.
+    pub weights: Vec<f32>,
+    pub bias: Vec<f32>,
+}
+
+/// All parameters of a `TDModel`, see the module documentation for the file formats.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weights {
+    pub activation: Activation,
+    pub encoding: Encoding,
+    /// Hidden layers followed by the output layer.
+    pub layers: Vec<LayerWeights>,
+}
+
+/// The JSON document, names are stored instead of enum variants.
+#[derive(Serialize, Deserialize)]
+struct Document {
+    format: String,
+    version: u32,
+    activation: String,
+    encoding: String,
+    layers: Vec<LayerWeights>,
+}
+
+impl Weights {
+    pub fn from_model<B: Backend>(model: &TDModel<B>) -> Self {
+        let layers = model
+            .layers()
+            .into_iter()
+            .map(|linear| {
+                let weights: Data<f32, 2> = linear.weight.val().into_data().convert();
+                let [inputs, outputs] = weights.shape.dims;
+                let bias = match &linear.bias {
+                    Some(bias) => {
+                        let bias: Data<f32, 1> = bias.val().into_data().convert();
+                        bias.value
+                    }
+                    None => vec![0.0; outputs],
+                };
+                LayerWeights {
+                    inputs,
+                    outputs,
+                    weights: weights.value,
+                    bias,
+                }
+            })
+            .collect();
+        Self {
+            activation: model.activation(),
+            encoding: model.encoding(),
+            layers,
+        }
+    }
+
+    pub fn to_model<B: Backend>(&self, device: &B::Device) -> Result<TDModel<B>, String> {
+        self.validate()?;
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| {
+                let shape = Shape::new([layer.inputs, layer.outputs]);
+                let weight = Data::new(layer.weights.clone(), shape);
+                let bias = Data::new(layer.bias.clone(), Shape::new([layer.outputs]));
+                nn::Linear {
+                    weight: Param::from(Tensor::from_data(weight.convert(), device)),
+                    bias: Some(Param::from(Tensor::from_data(bias.convert(), device))),
+                }
+            })
+            .collect();
+        Ok(TDModel::from_layers(layers, self.activation, self.encoding))
+    }
+
+    pub fn to_cpu(&self) -> Result<CpuModel, String> {
+        self.validate()?;
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| Layer::new(layer.inputs, layer.outputs, &layer.weights, &layer.bias))
+            .collect();
+        Ok(CpuModel::new(layers, self.activation, self.encoding))
+    }
+
+    /// Checks that the layers fit together, imported weights may come from anywhere.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.layers.len() < 2 {
+            return Err("Needs at least one hidden and one output layer".to_string());
+        }
+        if self.layers[0].inputs != self.encoding.num_inputs() {
+            return Err(format!(
+                "The {} encoding has {} inputs, the first layer {}",
+                self.encoding.name(),
+                self.encoding.num_inputs(),
+                self.layers[0].inputs
+            ));
+        }
+        for (i, layer) in self.layers.iter().enumerate() {
+            if layer.weights.len() != layer.inputs * layer.outputs {
+                return Err(format!(
+                    "Layer {} has {} weights, expected {}",
+                    i,
+                    layer.weights.len(),
+                    layer.inputs * layer.outputs
+                ));
+            }
+            if layer.bias.len() != layer.outputs {
+                return Err(format!(
+                    "Layer {} has {} biases, expected {}",
+                    i,
+                    layer.bias.len(),
+                    layer.outputs
+                ));
+            }
+        }
+        for (i, pair) in self.layers.windows(2).enumerate() {
+            if pair[0].outputs != pair[1].inputs {
+                return Err(format!(
+                    "Layer {} has {} outputs, but layer {} has {} inputs",
+                    i,
+                    pair[0].outputs,
+                    i + 1,
+                    pair[1].inputs
+                ));
+            }
+        }
+        let outputs = self.layers[self.layers.len() - 1].outputs;
+        if outputs != 1 && outputs != 5 {
+            return Err(format!("Expected 1 or 5 outputs, got {}", outputs));
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let document = Document {
+            format: FORMAT.to_string(),
+            version: WEIGHTS_VERSION,
+            activation: self.activation.name().to_string(),
+            encoding: self.encoding.name().to_string(),
+            layers: self.layers.clone(),
+        };
+        serde_json::to_string(&document).expect("Failed to serialize weights")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let document: Document =
+            serde_json::from_str(json).map_err(|e| format!("Invalid weights: {}", e))?;
+        if document.format != FORMAT {
+            return Err(format!("Unknown format '{}'", document.format));
+        }
+        check_version(document.version)?;
+        let weights = Self {
+            activation: Activation::from_str(&document.activation)?,
+            encoding: Encoding::from_str(&document.encoding)?,
+            layers: document.layers,
+        };
+        weights.validate()?;
+        Ok(weights)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(WEIGHTS_VERSION.to_le_bytes());
+        for name in [self.activation.name(), self.encoding.name()] {
+            bytes.push(name.len() as u8);
+            bytes.extend(name.as_bytes());
+        }
+        bytes.extend((self.layers.len() as u32).to_le_bytes());
+        for layer in &self.layers {
+            bytes.extend((layer.inputs as u32).to_le_bytes());
+            bytes.extend((layer.outputs as u32).to_le_bytes());
+            for value in layer.weights.iter().chain(&layer.bias) {
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != MAGIC {
+            return Err("Not a weights file, the header is missing".to_string());
+        }
+        check_version(reader.u32()?)?;
+        let activation = Activation::from_str(&reader.name()?)?;
+        let encoding = Encoding::from_str(&reader.name()?)?;
+        let num_layers = reader.u32()? as usize;
+        let mut layers = Vec::with_capacity(num_layers.min(16));
+        for _ in 0..num_layers {
+            let inputs = reader.u32()? as usize;
+            let outputs = reader.u32()? as usize;
+            layers.push(LayerWeights {
+                inputs,
+                outputs,
+                weights: reader.f32s(inputs * outputs)?,
+                bias: reader.f32s(outputs)?,
+            });
+        }
+        if !reader.bytes.is_empty() {
+            return Err(format!("{} bytes after the last layer", reader.bytes.len()));
+        }
+        let weights = Self {
+            activation,
+            encoding,
+            layers,
+        };
+        weights.validate()?;
+        Ok(weights)
+    }
+
+    /// JSON if the extension is `json`, binary otherwise.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let bytes = if is_json(path) {
+            self.to_json().into_bytes()
+        } else {
+            self.to_bytes()
+        };
+        std::fs::write(path, bytes)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// JSON if the extension is `json`, binary otherwise.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if is_json(path) {
+            let json = String::from_utf8(bytes).map_err(|e| format!("Invalid weights: {}", e))?;
+            Self::from_json(&json)
+        } else {
+            Self::from_bytes(&bytes)
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+fn check_version(version: u32) -> Result<(), String> {
+    if version > WEIGHTS_VERSION {
+        return Err(format!(
+            "Weights version {} is newer than the supported version {}",
+            version, WEIGHTS_VERSION
+        ));
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < n {
+            return Err("Weights file ends too early".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let len = self.take(1)?[0] as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| format!("Invalid name: {}", e))
+    }
+
+    fn f32s(&mut self, n: usize) -> Result<Vec<f32>, String> {
+        let bytes = self.take(n.checked_mul(4).ok_or("Layer too large")?)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{device, DefaultBackend};
+    use crate::cpu::CpuModel;
+    use crate::inputs::Encoding;
+    use crate::model::{Activation, ModelConfig, TDModel};
+    use crate::weights::{LayerWeights, Weights};
+
+    fn weights() -> Weights {
+        let config = ModelConfig::new()
+            .with_widths(vec![6, 4])
+            .with_activation(Activation::Tanh)
+            .with_encoding(Encoding::Tesauro);
+        let model = TDModel::<DefaultBackend>::new(config, &device(true));
+        Weights::from_model(&model)
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let weights = weights();
+        assert_eq!(Weights::from_json(&weights.to_json()), Ok(weights));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let weights = weights();
+        let bytes = weights.to_bytes();
+        assert_eq!(&bytes[..4], b"TDGW");
+        assert_eq!(Weights::from_bytes(&bytes), Ok(weights));
+        assert!(Weights::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Weights::from_bytes(b"mpk").is_err());
+    }
+
+    #[test]
+    fn model_round_trip() {
+        let weights = weights();
+        let model = weights.to_model::<DefaultBackend>(&device(true)).unwrap();
+        assert_eq!(Weights::from_model(&model), weights);
+        assert_eq!(weights.to_cpu().unwrap(), CpuModel::from_model(&model));
+    }
+
+    #[test]
+    fn invalid_layers() {
+        let mut weights = weights();
+        weights.layers[1] = LayerWeights {
+            inputs: 5,
+            outputs: 4,
+            weights: vec![0.0; 20],
+            bias: vec![0.0; 4],
+        };
+        assert_eq!(
+            weights.validate(),
+            Err("Layer 0 has 6 outputs, but layer 1 has 5 inputs".to_string())
+        );
+        assert!(Weights::from_json(&weights.to_json()).is_err());
+    }
+}